@@ -0,0 +1,162 @@
+use std::f64::consts::TAU;
+
+use nannou::{
+    noise::{NoiseFn, OpenSimplex},
+    prelude::ToPrimitive,
+    rand::{random_f32, random_range},
+};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
+
+use crate::particles::Particle;
+
+#[derive(PartialEq, Clone, Copy, Debug, EnumIter, Display, Serialize, Deserialize)]
+pub enum AmbientStyle {
+    /// All fixtures slowly brighten and dim together
+    Breathing,
+    /// Random fixtures briefly glow, then fade
+    Twinkle,
+    /// Smooth waves of brightness drift along the row
+    NoiseWaves,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct AmbientSettings {
+    pub enabled: bool,
+    /// Start the ambient layer after this long (ms) without any triggers
+    pub idle_timeout: u64,
+    pub style: AmbientStyle,
+    /// Brightest the ambient layer gets
+    pub level: f32,
+    /// Length (ms) of a breath, a twinkle fading out, or a wave passing
+    pub period: u64,
+    /// Time (ms) to fade the ambient layer in, or out when a trigger arrives
+    pub fade_duration: u64,
+    /// Average number of twinkles per second, across all the fixtures
+    pub twinkle_rate: f32,
+}
+
+impl Default for AmbientSettings {
+    fn default() -> Self {
+        AmbientSettings {
+            enabled: false,
+            idle_timeout: 60000,
+            style: AmbientStyle::Breathing,
+            level: 0.3,
+            period: 8000,
+            fade_duration: 2000,
+            twinkle_rate: 1.,
+        }
+    }
+}
+
+/// A generative layer that takes over when nobody is interacting. Each
+/// fixture shows whichever is brighter: its own animation, or this layer.
+pub struct Ambient {
+    /// Time (ms) since the last trigger
+    idle_for: u64,
+    /// How far the layer has faded in, 0-1
+    mix: f32,
+    /// Time (ms) the layer has been running, which drives the animation
+    time: f64,
+    /// Current level of each twinkle, in the same order as the particles
+    twinkles: Vec<f32>,
+    noise: OpenSimplex,
+}
+
+impl Default for Ambient {
+    fn default() -> Self {
+        Ambient {
+            idle_for: 0,
+            mix: 0.,
+            time: 0.,
+            twinkles: Vec::new(),
+            noise: OpenSimplex::new(),
+        }
+    }
+}
+
+impl Ambient {
+    /// A trigger arrived, so fade out (if running) and restart the idle timeout
+    pub fn notice_activity(&mut self) {
+        self.idle_for = 0;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.mix > 0.
+    }
+
+    /// Time (ms) until the layer starts, if it is waiting to
+    pub fn starts_in(&self, settings: &AmbientSettings) -> Option<u64> {
+        if settings.enabled && !self.is_active() {
+            Some(settings.idle_timeout.saturating_sub(self.idle_for))
+        } else {
+            None
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        settings: &AmbientSettings,
+        delta_time: usize,
+        particles: &mut [Particle],
+    ) {
+        let delta_time = delta_time.to_u64().unwrap();
+        self.idle_for = self.idle_for.saturating_add(delta_time);
+
+        let fade_step =
+            delta_time.to_f32().unwrap() / settings.fade_duration.max(1).to_f32().unwrap();
+        self.mix = if settings.enabled && self.idle_for >= settings.idle_timeout {
+            (self.mix + fade_step).min(1.)
+        } else {
+            (self.mix - fade_step).max(0.)
+        };
+
+        if self.mix == 0. {
+            self.time = 0.;
+            self.twinkles.clear();
+            for p in particles {
+                p.set_ambient(0.);
+            }
+            return;
+        }
+
+        self.time += delta_time.to_f64().unwrap();
+        let period = settings.period.max(1).to_f64().unwrap();
+        let phase = self.time / period;
+
+        if settings.style == AmbientStyle::Twinkle {
+            self.update_twinkles(settings, delta_time, particles.len());
+        }
+
+        for (i, p) in particles.iter_mut().enumerate() {
+            let level = match settings.style {
+                AmbientStyle::Breathing => (0.5 - 0.5 * (phase * TAU).cos()).to_f32().unwrap(),
+                AmbientStyle::Twinkle => self.twinkles.get(i).copied().unwrap_or(0.),
+                AmbientStyle::NoiseWaves => {
+                    // Neighbouring fixtures sample nearby points in the noise field
+                    let value = self.noise.get([p.order.to_f64().unwrap() * 0.3, phase]);
+                    (value * 0.5 + 0.5).clamp(0., 1.).to_f32().unwrap()
+                }
+            };
+            p.set_ambient(level * settings.level * self.mix);
+        }
+    }
+
+    fn update_twinkles(&mut self, settings: &AmbientSettings, delta_time: u64, count: usize) {
+        self.twinkles.resize(count, 0.);
+
+        // Fade each twinkle out over (roughly) one period
+        let decay =
+            (-(delta_time.to_f32().unwrap()) * 4. / settings.period.max(1).to_f32().unwrap()).exp();
+        for level in &mut self.twinkles {
+            *level *= decay;
+        }
+
+        let chance = settings.twinkle_rate * delta_time.to_f32().unwrap() / 1000.;
+        if count > 0 && random_f32() < chance {
+            self.twinkles[random_range(0, count)] = 1.;
+        }
+    }
+}
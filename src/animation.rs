@@ -1,11 +1,18 @@
 use nannou::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
 use tween::{Tween, Tweener};
 
+use crate::settings::{get_new_tween, EaseStyle, PhaseSettings};
+
 type StoredTweener = Tweener<f32, usize, Box<dyn Tween<f32>>>;
 
 pub struct Animation {
     pub elapsed: i64,
     pub duration: usize,
+    /// Time (ms) to stay at the final value once the tween has finished,
+    /// before the animation is done
+    pub hold: usize,
     pub tweener: StoredTweener,
 }
 
@@ -19,6 +26,7 @@ impl Animation {
         Animation {
             duration,
             elapsed: 0,
+            hold: 0,
             tweener: Tweener::new(start_brightness, target_brightness, duration, tween),
         }
     }
@@ -45,9 +53,20 @@ impl Animation {
         }
     }
 
+    /// Hold the final value for long enough that the animation is not done
+    /// until `remaining` ms from now
+    pub fn hold_for(&mut self, remaining: usize) {
+        let end = self.elapsed + remaining.to_i64().unwrap_or(i64::MAX);
+        let hold = (end - self.duration.to_i64().unwrap_or(i64::MAX))
+            .to_usize()
+            .unwrap_or(0);
+        self.hold = self.hold.max(hold);
+    }
+
     pub fn get_brightness_and_done(&mut self, delta_time: usize) -> (f32, bool) {
         if let Some(brightness) = self.update(delta_time) {
-            (brightness, self.tweener.is_finished())
+            let held = self.elapsed >= (self.duration + self.hold).to_i64().unwrap_or(i64::MAX);
+            (brightness, self.tweener.is_finished() && held)
         } else {
             (self.tweener.initial_value(), false)
         }
@@ -56,15 +75,150 @@ impl Animation {
 
 pub struct AfterAttack {
     pub release_duration: usize,
+    pub release_style: EaseStyle,
     pub final_brightness: f32,
 }
 
 // The animation concept is based on https://en.wikipedia.org/wiki/Envelope_(music)
 pub enum EnvelopeStage {
     /// The Attack animation to play, followed by the
-    /// (optional) duration and final brightness of the
+    /// (optional) duration, style and final brightness of the
     /// Release animation that follows
     AttackAnimation(Animation, Option<AfterAttack>),
     ReleaseAnimation(Animation),
     Idle(),
 }
+
+impl EnvelopeStage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnvelopeStage::AttackAnimation(..) => "attack",
+            EnvelopeStage::ReleaseAnimation(..) => "release",
+            EnvelopeStage::Idle() => "idle",
+        }
+    }
+
+    /// Keep the envelope up for at least another attack: a running attack
+    /// holds its peak until `attack.duration` from now before it releases,
+    /// and a release goes back up to where it started from (then releases
+    /// again over `release`, to the same final brightness). Returns false if
+    /// the envelope is idle, so there is nothing to extend.
+    pub fn extend(&mut self, level: f32, attack: &PhaseSettings, release: &PhaseSettings) -> bool {
+        match self {
+            EnvelopeStage::AttackAnimation(a, _) => {
+                a.hold_for(attack.duration);
+                true
+            }
+            EnvelopeStage::ReleaseAnimation(a) => {
+                let peak = a.tweener.initial_value();
+                let final_brightness = a.tweener.final_value();
+                *self = EnvelopeStage::AttackAnimation(
+                    Animation::new(attack.duration, level, peak, get_new_tween(&attack.style)),
+                    Some(AfterAttack {
+                        release_duration: release.duration,
+                        release_style: release.style,
+                        final_brightness,
+                    }),
+                );
+                true
+            }
+            EnvelopeStage::Idle() => false,
+        }
+    }
+
+    /// Move the envelope on by `delta_time` ms, going from the attack to the
+    /// release (which starts from `level`) and then to idle. Returns the new
+    /// level, if the envelope has set one.
+    pub fn advance(
+        &mut self,
+        delta_time: usize,
+        level: f32,
+        release_settings: &PhaseSettings,
+    ) -> Option<f32> {
+        match self {
+            EnvelopeStage::AttackAnimation(a, after_attack) => {
+                let (brightness, done) = a.get_brightness_and_done(delta_time);
+                if done {
+                    let (duration, style, final_brightness) = match after_attack {
+                        Some(after) => (
+                            after.release_duration,
+                            after.release_style,
+                            after.final_brightness,
+                        ),
+                        None => (release_settings.duration, release_settings.style, 0.),
+                    };
+                    *self = EnvelopeStage::ReleaseAnimation(Animation::new(
+                        duration,
+                        level,
+                        final_brightness,
+                        get_new_tween(&style),
+                    ));
+                    None
+                } else {
+                    Some(brightness)
+                }
+            }
+            EnvelopeStage::ReleaseAnimation(a) => {
+                let (brightness, done) = a.get_brightness_and_done(delta_time);
+                if done {
+                    *self = EnvelopeStage::Idle();
+                }
+                Some(brightness)
+            }
+            EnvelopeStage::Idle() => None,
+        }
+    }
+}
+
+/// How an animation layer combines with the layers below it
+#[derive(PartialEq, Clone, Copy, Debug, EnumIter, Display, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BlendMode {
+    /// Whichever is brighter
+    Max,
+    /// Added together, up to full brightness
+    Add,
+    /// Scales the brightness below; the layer rises (or falls) from 1,
+    /// so a target below 1 dims the fixture for a while
+    Multiply,
+    /// Replaces everything below, while the layer lasts
+    Override,
+}
+
+/// Play an activation in a layer of its own, on top of the fixture's main
+/// envelope, instead of replacing it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LayerOptions {
+    pub blend: BlendMode,
+    /// Layers are applied from the lowest priority to the highest; layers
+    /// with the same priority in the order they started
+    #[serde(default)]
+    pub priority: i32,
+}
+
+pub struct AnimationLayer {
+    pub envelope: EnvelopeStage,
+    pub options: LayerOptions,
+    pub level: f32,
+}
+
+impl AnimationLayer {
+    /// The level of a layer that makes no difference to the layers below
+    pub fn neutral_level(blend: BlendMode) -> f32 {
+        match blend {
+            BlendMode::Multiply => 1.,
+            BlendMode::Max | BlendMode::Add | BlendMode::Override => 0.,
+        }
+    }
+
+    /// Apply this layer on top of the brightness of the layers below
+    pub fn blend(&self, below: f32) -> f32 {
+        match self.options.blend {
+            BlendMode::Max => below.max(self.level),
+            BlendMode::Add => (below + self.level).min(1.),
+            BlendMode::Multiply => below * self.level,
+            BlendMode::Override => self.level,
+        }
+    }
+}
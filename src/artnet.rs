@@ -72,12 +72,19 @@ impl ArtNetInterface {
         self.brightness_mapping = Some(lookup);
     }
 
-    pub fn update(&self, particles: &[Particle], channels_per_fixture: usize, use_high_res: bool) {
+    /// Send the fixtures' brightness on an Art-Net universe (port address)
+    pub fn update(
+        &self,
+        particles: &[Particle],
+        channels_per_fixture: usize,
+        use_high_res: bool,
+        universe: u8,
+    ) {
         let mut channels: Vec<u8> = vec![];
         if use_high_res {
             for p in particles {
                 for _i in 0..channels_per_fixture {
-                    let l_sixteen: u16 = (p.brightness() * SIXTEEN_MAX) as u16;
+                    let l_sixteen: u16 = (p.output_brightness() * SIXTEEN_MAX) as u16;
                     let [c1, c2] = l_sixteen.to_be_bytes();
                     channels.push(c1);
                     channels.push(c2);
@@ -86,7 +93,8 @@ impl ArtNetInterface {
         } else {
             for p in particles {
                 for _i in 0..channels_per_fixture {}
-                if let Some(brightness) = map_range(p.brightness(), 0., 1., 0., 255.).to_u8() {
+                if let Some(brightness) = map_range(p.output_brightness(), 0., 1., 0., 255.).to_u8()
+                {
                     match self.brightness_mapping {
                         Some(lookup) => {
                             channels.push(lookup[brightness.to_usize().unwrap()]);
@@ -100,7 +108,7 @@ impl ArtNetInterface {
         }
 
         let command = ArtCommand::Output(Output {
-            port_address: 0.into(),
+            port_address: universe.into(),
             data: channels.into(),
             ..Output::default()
         });
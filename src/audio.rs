@@ -0,0 +1,449 @@
+use std::{
+    f32::consts::PI,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use log::{info, warn};
+use nannou::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
+
+use crate::settings::DEFAULT_WIDTH_RATIO;
+use crate::tether::{
+    LightMessages, LightPositionTriggerMessage, PositionMode, PositionUnits, ScheduleOptions,
+    TriggerOptions,
+};
+
+/// Length (ms) of each frame of analysis
+const FRAME_MS: u64 = 10;
+
+/// Range of the band centre frequencies (Hz); the top is also kept below
+/// the Nyquist frequency of the file
+const LOWEST_BAND: f32 = 60.;
+const HIGHEST_BAND: f32 = 8000.;
+
+/// How quickly (ms) the average flux that onsets are compared to follows
+/// the music
+const FLUX_AVERAGE_TIME: f32 = 1000.;
+
+/// Rises in level smaller than this never count as onsets, so that near
+/// silence doesn't trigger anything
+const MIN_FLUX: f32 = 0.02;
+
+#[derive(PartialEq, Clone, Copy, Debug, EnumIter, Display, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AudioMapping {
+    /// The row is split into one section per band, from low frequencies on
+    /// the left to high on the right; an onset in a band lights its section
+    Bands,
+    /// Every onset lights the fixture at the centre of its frequencies, so
+    /// a kick drum lands on the left and a hi-hat on the right
+    Onsets,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioSettings {
+    pub mapping: AudioMapping,
+    /// Number of frequency bands; used when a file is loaded
+    pub bands: usize,
+    /// How far above its recent average a rise in level must be to count
+    /// as an onset; higher finds fewer onsets
+    pub sensitivity: f32,
+    /// Shortest time (ms) between onsets in the same band
+    pub min_gap: u64,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            mapping: AudioMapping::Bands,
+            bands: 6,
+            sensitivity: 2.,
+            min_gap: 100,
+        }
+    }
+}
+
+/// Analysis of one frame
+struct Frame {
+    /// Level of each band, 0-1 relative to the loudest that band gets in the file
+    levels: Vec<f32>,
+    /// Rise in each band's level since the previous frame
+    flux: Vec<f32>,
+    /// Recent average of each band's flux, before this frame
+    average_flux: Vec<f32>,
+}
+
+/// A band-pass biquad (constant 0 dB peak gain)
+struct BandPass {
+    b0: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    x: [f32; 2],
+    y: [f32; 2],
+}
+
+impl BandPass {
+    fn new(sample_rate: f32, frequency: f32, q: f32) -> Self {
+        let w0 = 2. * PI * frequency / sample_rate;
+        let alpha = w0.sin() / (2. * q);
+        let a0 = 1. + alpha;
+        BandPass {
+            b0: alpha / a0,
+            b2: -alpha / a0,
+            a1: -2. * w0.cos() / a0,
+            a2: (1. - alpha) / a0,
+            x: [0.; 2],
+            y: [0.; 2],
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let output =
+            self.b0 * input + self.b2 * self.x[1] - self.a1 * self.y[0] - self.a2 * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+/// A WAV file being read and analysed on a background thread, since long
+/// files take a while and would otherwise freeze the window
+pub struct PendingAudio {
+    path: String,
+    result: Receiver<Result<AudioPlayer, String>>,
+    /// Applied to the player once it is ready
+    pub looping: bool,
+    pub playing: bool,
+}
+
+impl PendingAudio {
+    pub fn start(path: &str, bands: usize) -> Self {
+        let (sender, result) = mpsc::channel();
+        let thread_path = String::from(path);
+        thread::spawn(move || {
+            // The receiver is gone if another file was loaded meanwhile
+            sender.send(AudioPlayer::load(&thread_path, bands)).ok();
+        });
+        info!("Analysing {path} in the background");
+        PendingAudio {
+            path: String::from(path),
+            result,
+            looping: false,
+            playing: false,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The player once the analysis has finished, or why it failed; None
+    /// while it is still running
+    pub fn poll(&self) -> Option<Result<AudioPlayer, String>> {
+        match self.result.try_recv() {
+            Ok(Ok(mut player)) => {
+                player.looping = self.looping;
+                player.playing = self.playing;
+                Some(Ok(player))
+            }
+            Ok(Err(e)) => Some(Err(e)),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(format!(
+                "analysis of {} stopped unexpectedly",
+                self.path
+            ))),
+        }
+    }
+}
+
+/// Plays an analysed WAV file as position triggers, in step with the
+/// engine clock rather than the sound card, so it runs the same with or
+/// without graphics
+pub struct AudioPlayer {
+    path: String,
+    frames: Vec<Frame>,
+    bands: usize,
+    /// Playback position in ms
+    position: u64,
+    /// Index of the next frame to play
+    next: usize,
+    /// Time (ms) of the last onset in each band, and overall
+    last_onsets: Vec<Option<u64>>,
+    pub looping: bool,
+    pub playing: bool,
+}
+
+impl AudioPlayer {
+    /// Read and analyse a WAV file, splitting it into `bands` frequency bands
+    pub fn load(path: &str, bands: usize) -> Result<Self, String> {
+        if !(1..=32).contains(&bands) {
+            return Err(format!("bands must be between 1 and 32, got {bands}"));
+        }
+        let (samples, sample_rate) = read_mono(path)?;
+        let frames = analyse(&samples, sample_rate, bands);
+        info!(
+            "Analysed {path}: {:.1}s in {bands} bands",
+            samples.len().to_f32().unwrap() / sample_rate
+        );
+        Ok(AudioPlayer {
+            path: String::from(path),
+            frames,
+            bands,
+            position: 0,
+            next: 0,
+            last_onsets: vec![None; bands + 1],
+            looping: false,
+            playing: false,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn bands(&self) -> usize {
+        self.bands
+    }
+
+    /// Length of the file, in ms
+    pub fn duration(&self) -> u64 {
+        self.frames.len().to_u64().unwrap() * FRAME_MS
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Levels of the bands at the current position, e.g. to show as meters
+    pub fn levels(&self) -> &[f32] {
+        self.frames
+            .get(self.next.saturating_sub(1))
+            .map_or(&[], |frame| &frame.levels)
+    }
+
+    /// Jump to a time (ms) in the file, without playing the onsets in between
+    pub fn seek(&mut self, position: u64) {
+        self.position = position.min(self.duration());
+        self.next = (self.position / FRAME_MS).to_usize().unwrap();
+        self.last_onsets.fill(None);
+    }
+
+    /// Move playback on by `delta_time` ms, returning triggers for the
+    /// onsets that are now due
+    pub fn advance(&mut self, delta_time: usize, settings: &AudioSettings) -> Vec<LightMessages> {
+        let mut due = Vec::new();
+        if !self.playing {
+            return due;
+        }
+        self.position += delta_time.to_u64().unwrap();
+
+        loop {
+            while self.next < self.frames.len() {
+                let time = self.next.to_u64().unwrap() * FRAME_MS;
+                if time > self.position {
+                    return due;
+                }
+                due.extend(self.frame_triggers(self.next, time, settings));
+                self.next += 1;
+            }
+
+            // Reached the end
+            let duration = self.duration();
+            if self.looping && duration > 0 {
+                self.position -= duration;
+                self.next = 0;
+                self.last_onsets.fill(None);
+            } else {
+                info!("Finished playing {}", self.path);
+                self.playing = false;
+                self.seek(0);
+                return due;
+            }
+        }
+    }
+
+    /// Whether an onset can happen at `time` (ms) in this band (or in
+    /// `bands`, for onsets across all of them), given when the last one was
+    fn allow_onset(&mut self, band: usize, time: u64, settings: &AudioSettings) -> bool {
+        let allowed =
+            self.last_onsets[band].is_none_or(|last| time.saturating_sub(last) >= settings.min_gap);
+        if allowed {
+            self.last_onsets[band] = Some(time);
+        }
+        allowed
+    }
+
+    fn frame_triggers(
+        &mut self,
+        index: usize,
+        time: u64,
+        settings: &AudioSettings,
+    ) -> Vec<LightMessages> {
+        let frame = &self.frames[index];
+        let is_onset =
+            |flux: f32, average: f32| flux > MIN_FLUX && flux > average * settings.sensitivity;
+        let bands = self.bands.to_f32().unwrap();
+        let mut triggers = Vec::new();
+
+        match settings.mapping {
+            AudioMapping::Bands => {
+                let onsets: Vec<(usize, f32)> = (0..self.bands)
+                    .filter(|&b| is_onset(frame.flux[b], frame.average_flux[b]))
+                    .map(|b| (b, frame.levels[b]))
+                    .collect();
+                for (band, level) in onsets {
+                    if self.allow_onset(band, time, settings) {
+                        // Transmission spreads the trigger across the band's section
+                        let options = TriggerOptions {
+                            transmission_range: Some(DEFAULT_WIDTH_RATIO * 0.5 / bands),
+                            ..TriggerOptions::default()
+                        };
+                        let x = (band.to_f32().unwrap() + 0.5) / bands;
+                        triggers.push(position_trigger(x, level, options));
+                    }
+                }
+            }
+            AudioMapping::Onsets => {
+                let flux: f32 = frame.flux.iter().sum();
+                let average: f32 = frame.average_flux.iter().sum();
+                if is_onset(flux, average) {
+                    // The centroid of the bands that rose, low on the left
+                    let centre = frame
+                        .flux
+                        .iter()
+                        .enumerate()
+                        .map(|(b, f)| (b.to_f32().unwrap() + 0.5) * f)
+                        .sum::<f32>()
+                        / flux;
+                    let level = frame.levels.iter().copied().fold(0., f32::max);
+                    if self.allow_onset(self.bands, time, settings) {
+                        triggers.push(position_trigger(
+                            centre / bands,
+                            level,
+                            TriggerOptions::default(),
+                        ));
+                    }
+                }
+            }
+        }
+        triggers
+    }
+}
+
+fn position_trigger(x: f32, brightness: f32, options: TriggerOptions) -> LightMessages {
+    LightMessages::PositionTrigger(LightPositionTriggerMessage {
+        x: x.clamp(0., 1.),
+        units: PositionUnits::Normalised,
+        mode: PositionMode::Nearest,
+        target_brightness: brightness.clamp(0., 1.),
+        options,
+        schedule: ScheduleOptions::default(),
+    })
+}
+
+/// All the samples of a WAV file mixed down to one channel (-1 to 1),
+/// with the sample rate
+fn read_mono(path: &str) -> Result<(Vec<f32>, f32), String> {
+    let mut reader =
+        hound::WavReader::open(path).map_err(|e| format!("failed to open {path}: {e}"))?;
+    let spec = reader.spec();
+    let channels = usize::from(spec.channels.max(1));
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("failed to read {path}: {e}"))?,
+        hound::SampleFormat::Int => {
+            let scale = 1. / (1_i64 << (spec.bits_per_sample - 1)).to_f32().unwrap();
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s.to_f32().unwrap() * scale))
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("failed to read {path}: {e}"))?
+        }
+    };
+    if interleaved.is_empty() {
+        warn!("{path} has no samples");
+    }
+    let mono = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels.to_f32().unwrap())
+        .collect();
+    Ok((mono, spec.sample_rate.to_f32().unwrap()))
+}
+
+/// Split the samples into frames, and measure the level of each band in
+/// each frame and how much it rose
+fn analyse(samples: &[f32], sample_rate: f32, bands: usize) -> Vec<Frame> {
+    let highest = HIGHEST_BAND.min(sample_rate * 0.45);
+    // Centre frequencies evenly spaced in pitch, each band about as wide
+    // as the step between them
+    let step = if bands > 1 {
+        (highest / LOWEST_BAND).powf(1. / (bands - 1).to_f32().unwrap())
+    } else {
+        2.
+    };
+    let q = step.sqrt() / (step - 1.);
+    let mut filters: Vec<BandPass> = (0..bands)
+        .map(|b| {
+            let frequency = LOWEST_BAND * step.powi(b.to_i32().unwrap());
+            BandPass::new(sample_rate, frequency, q)
+        })
+        .collect();
+
+    let frame_length = (sample_rate * FRAME_MS.to_f32().unwrap() / 1000.)
+        .round()
+        .max(1.)
+        .to_usize()
+        .unwrap();
+    let rms: Vec<Vec<f32>> = samples
+        .chunks(frame_length)
+        .map(|chunk| {
+            filters
+                .iter_mut()
+                .map(|filter| {
+                    let sum: f32 = chunk.iter().map(|s| filter.process(*s).powi(2)).sum();
+                    (sum / chunk.len().to_f32().unwrap()).sqrt()
+                })
+                .collect()
+        })
+        .collect();
+
+    let loudest: Vec<f32> = (0..bands)
+        .map(|b| {
+            rms.iter()
+                .map(|r| r[b])
+                .fold(0., f32::max)
+                .max(f32::EPSILON)
+        })
+        .collect();
+    let smoothing = FRAME_MS.to_f32().unwrap() / FLUX_AVERAGE_TIME;
+
+    let mut frames: Vec<Frame> = Vec::with_capacity(rms.len());
+    let mut average_flux = vec![0.; bands];
+    for r in &rms {
+        let levels: Vec<f32> = r.iter().zip(&loudest).map(|(r, l)| r / l).collect();
+        let flux: Vec<f32> = match frames.last() {
+            Some(previous) => levels
+                .iter()
+                .zip(&previous.levels)
+                .map(|(now, before)| (now - before).max(0.))
+                .collect(),
+            None => levels.clone(),
+        };
+        let frame = Frame {
+            levels,
+            flux,
+            average_flux: average_flux.clone(),
+        };
+        for (average, flux) in average_flux.iter_mut().zip(&frame.flux) {
+            *average += (flux - *average) * smoothing;
+        }
+        frames.push(frame);
+    }
+    frames
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use nannou::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
+
+/// What to do with a trigger for a fixture that was triggered less than
+/// the debounce window ago
+#[derive(PartialEq, Clone, Copy, Debug, EnumIter, Display, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DebouncePolicy {
+    /// Drop it; the window starts again only with the next accepted trigger
+    Ignore,
+    /// Hold the brightest one, and fire it once when the window ends
+    Coalesce,
+    /// Keep the fixture lit: if it is still in its attack, hold the peak
+    /// until another attack duration has passed before releasing; if it is
+    /// already releasing, go back up. Applies to the main envelope, or to
+    /// the fixture's layer if the trigger plays in one. Each one starts the
+    /// window again.
+    Extend,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct DebounceSettings {
+    /// Time (ms) after a trigger during which further triggers of the same
+    /// fixture are duplicates; 0 turns debouncing off
+    pub window: u64,
+    pub policy: DebouncePolicy,
+}
+
+impl Default for DebounceSettings {
+    fn default() -> Self {
+        DebounceSettings {
+            window: 0,
+            policy: DebouncePolicy::Ignore,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Verdict {
+    /// Not a duplicate (or debouncing is off); trigger as normal
+    Trigger,
+    Ignore,
+    Extend,
+    /// Pass the trigger to `hold`, to fire when the window ends
+    Hold,
+}
+
+struct FixtureDebounce<T> {
+    /// Time (ms) since the fixture was last triggered
+    since: u64,
+    /// The brightest trigger held back, with the window it is waiting for
+    pending: Option<(f32, u64, T)>,
+}
+
+/// Tracks recent triggers of each fixture (by #ID), holding back any
+/// triggers being coalesced until their window ends
+pub struct Debouncer<T> {
+    fixtures: HashMap<usize, FixtureDebounce<T>>,
+}
+
+impl<T> Default for Debouncer<T> {
+    fn default() -> Self {
+        Debouncer {
+            fixtures: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Debouncer<T> {
+    /// Decide what to do with a trigger for the fixture, according to the
+    /// debounce settings it came with
+    pub fn check(&mut self, id: usize, settings: &DebounceSettings) -> Verdict {
+        let fixture = self.fixtures.entry(id).or_insert(FixtureDebounce {
+            since: u64::MAX,
+            pending: None,
+        });
+        if settings.window == 0 || fixture.since >= settings.window {
+            fixture.since = 0;
+            return Verdict::Trigger;
+        }
+        match settings.policy {
+            DebouncePolicy::Ignore => Verdict::Ignore,
+            DebouncePolicy::Coalesce => Verdict::Hold,
+            DebouncePolicy::Extend => {
+                fixture.since = 0;
+                Verdict::Extend
+            }
+        }
+    }
+
+    /// Hold back a trigger given `Verdict::Hold`, unless a brighter one is
+    /// already waiting
+    pub fn hold(&mut self, id: usize, brightness: f32, window: u64, trigger: T) {
+        if let Some(fixture) = self.fixtures.get_mut(&id) {
+            match &fixture.pending {
+                Some((held, ..)) if *held >= brightness => {}
+                _ => fixture.pending = Some((brightness, window, trigger)),
+            }
+        }
+    }
+
+    /// Move time on by `delta_time` ms, returning any held triggers whose
+    /// window has now ended
+    pub fn update(&mut self, delta_time: usize) -> Vec<T> {
+        let delta_time = delta_time.to_u64().unwrap();
+        let mut due = Vec::new();
+        for fixture in self.fixtures.values_mut() {
+            fixture.since = fixture.since.saturating_add(delta_time);
+            let window_ended = fixture
+                .pending
+                .as_ref()
+                .is_some_and(|(_, window, _)| fixture.since >= *window);
+            if window_ended {
+                if let Some((_, _, trigger)) = fixture.pending.take() {
+                    fixture.since = 0;
+                    due.push(trigger);
+                }
+            }
+        }
+        due
+    }
+}
@@ -0,0 +1,251 @@
+use std::f64::consts::TAU;
+
+use log::info;
+use nannou::{
+    noise::{NoiseFn, OpenSimplex},
+    prelude::ToPrimitive,
+    rand::{random_f32, random_range},
+};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
+
+use crate::{particles::Particle, tether::EffectParams};
+
+/// Hard limit on strobe flashes per second, whatever the settings say;
+/// flashing faster than 3Hz risks triggering photosensitive seizures
+pub const STROBE_RATE_LIMIT: f32 = 3.;
+
+/// Hard limit (ms) on how long a strobe runs, whatever the settings say
+pub const STROBE_DURATION_LIMIT: u64 = 10000;
+
+#[derive(PartialEq, Clone, Copy, Debug, EnumIter, Display, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EffectKind {
+    /// A lit spot (with a soft tail) that runs along the row
+    Chase,
+    /// A sine wave of brightness travelling along the row
+    Wave,
+    /// Random fixtures flash, then fade
+    Sparkle,
+    /// A drifting noise field
+    Noise,
+    /// All fixtures flash on and off together, within the safety limits
+    Strobe,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, EnumIter, Display, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChaseDirection {
+    /// From the first fixture in order to the last
+    Forward,
+    Backward,
+    /// Back and forth
+    Bounce,
+}
+
+/// Default parameters for effects; a message can override any of them
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct EffectSettings {
+    /// Brightest the effect gets
+    pub level: f32,
+    /// Chase: fixtures per second. Wave/noise: cycles per second.
+    /// Sparkle: how quickly each sparkle fades (per second).
+    pub speed: f32,
+    pub direction: ChaseDirection,
+    /// Chase: length (in fixtures) of the lit spot and its tail
+    pub width: f32,
+    /// Wave/noise: distance (in fixtures) between peaks
+    pub wavelength: f32,
+    /// Sparkle: sparkles per second. Strobe: flashes per second.
+    pub rate: f32,
+    /// Strobe rates above this (flashes per second) are reduced to it; can
+    /// only be lower than `STROBE_RATE_LIMIT`
+    pub max_strobe_rate: f32,
+    /// A strobe always stops after this long (ms); can only be lower than
+    /// `STROBE_DURATION_LIMIT`
+    pub max_strobe_duration: u64,
+}
+
+impl Default for EffectSettings {
+    fn default() -> Self {
+        EffectSettings {
+            level: 1.,
+            speed: 4.,
+            direction: ChaseDirection::Forward,
+            width: 1.5,
+            wavelength: 7.,
+            rate: 5.,
+            max_strobe_rate: STROBE_RATE_LIMIT,
+            max_strobe_duration: STROBE_DURATION_LIMIT,
+        }
+    }
+}
+
+impl EffectSettings {
+    /// These settings, with any overrides from a message applied,
+    /// and the strobe safety limits enforced
+    fn with_overrides(
+        &self,
+        kind: EffectKind,
+        params: &EffectParams,
+    ) -> (EffectSettings, Option<u64>) {
+        let mut resolved = EffectSettings {
+            level: params.level.unwrap_or(self.level),
+            speed: params.speed.unwrap_or(self.speed),
+            direction: params.direction.unwrap_or(self.direction),
+            width: params.width.unwrap_or(self.width),
+            wavelength: params.wavelength.unwrap_or(self.wavelength),
+            rate: params.rate.unwrap_or(self.rate),
+            ..*self
+        };
+        let mut duration = params.duration;
+        if kind == EffectKind::Strobe {
+            let max_rate = self.max_strobe_rate.min(STROBE_RATE_LIMIT);
+            let max_duration = self.max_strobe_duration.min(STROBE_DURATION_LIMIT);
+            resolved.rate = resolved.rate.min(max_rate);
+            duration = Some(duration.map_or(max_duration, |d| d.min(max_duration)));
+        }
+        (resolved, duration)
+    }
+}
+
+struct RunningEffect {
+    kind: EffectKind,
+    settings: EffectSettings,
+    /// Stop after this long (ms), or run until stopped
+    duration: Option<u64>,
+    /// Time (ms) since the effect started
+    elapsed: f64,
+}
+
+/// Procedural effects that run over the fixtures, as a layer above the
+/// envelopes: each fixture shows whichever is brighter. One effect runs
+/// at a time; starting another replaces it.
+pub struct Effects {
+    running: Option<RunningEffect>,
+    /// Chosen in the UI, to start with the current settings
+    pub selected: EffectKind,
+    /// Current level of each sparkle, in the same order as the particles
+    sparkles: Vec<f32>,
+    noise: OpenSimplex,
+}
+
+impl Default for Effects {
+    fn default() -> Self {
+        Effects {
+            running: None,
+            selected: EffectKind::Chase,
+            sparkles: Vec::new(),
+            noise: OpenSimplex::new(),
+        }
+    }
+}
+
+impl Effects {
+    pub fn start(&mut self, kind: EffectKind, defaults: &EffectSettings, params: &EffectParams) {
+        let (settings, duration) = defaults.with_overrides(kind, params);
+        info!("Start effect {kind} with {settings:?} for {duration:?}ms");
+        self.sparkles.clear();
+        self.running = Some(RunningEffect {
+            kind,
+            settings,
+            duration,
+            elapsed: 0.,
+        });
+    }
+
+    /// Stop the running effect, or only if it is of the given kind
+    pub fn stop(&mut self, kind: Option<EffectKind>) {
+        if kind.is_none() || kind == self.running() {
+            self.running = None;
+        }
+    }
+
+    pub fn running(&self) -> Option<EffectKind> {
+        self.running.as_ref().map(|effect| effect.kind)
+    }
+
+    pub fn update(&mut self, delta_time: usize, particles: &mut [Particle]) {
+        let effect = match &mut self.running {
+            Some(effect) => effect,
+            None => {
+                for p in particles {
+                    p.set_effect(0.);
+                }
+                return;
+            }
+        };
+
+        effect.elapsed += delta_time.to_f64().unwrap();
+        if let Some(duration) = effect.duration {
+            if effect.elapsed >= duration.to_f64().unwrap() {
+                info!("Effect {} finished", effect.kind);
+                self.running = None;
+                for p in particles {
+                    p.set_effect(0.);
+                }
+                return;
+            }
+        }
+
+        let settings = &effect.settings;
+        let seconds = effect.elapsed / 1000.;
+        let count = particles.len().to_f64().unwrap();
+        let speed = f64::from(settings.speed);
+        let wavelength = f64::from(settings.wavelength).max(0.1);
+
+        if effect.kind == EffectKind::Sparkle {
+            self.sparkles.resize(particles.len(), 0.);
+            let decay = (-settings.speed * delta_time.to_f32().unwrap() / 1000.).exp();
+            for level in &mut self.sparkles {
+                *level *= decay;
+            }
+            let chance = settings.rate * delta_time.to_f32().unwrap() / 1000.;
+            if !particles.is_empty() && random_f32() < chance {
+                self.sparkles[random_range(0, particles.len())] = 1.;
+            }
+        }
+
+        for (i, p) in particles.iter_mut().enumerate() {
+            let position = p.order.to_f64().unwrap();
+            let level = match effect.kind {
+                EffectKind::Chase => {
+                    let travelled = seconds * speed;
+                    let head = match settings.direction {
+                        ChaseDirection::Forward => travelled % count,
+                        ChaseDirection::Backward => count - 1. - travelled % count,
+                        ChaseDirection::Bounce => {
+                            let span = (count - 1.).max(1.);
+                            let t = travelled % (2. * span);
+                            if t < span {
+                                t
+                            } else {
+                                2. * span - t
+                            }
+                        }
+                    };
+                    let width = f64::from(settings.width).max(0.1);
+                    (1. - (position - head).abs() / width).max(0.)
+                }
+                EffectKind::Wave => {
+                    0.5 + 0.5 * ((position / wavelength - seconds * speed) * TAU).sin()
+                }
+                EffectKind::Sparkle => f64::from(self.sparkles[i]),
+                EffectKind::Noise => {
+                    let value = self.noise.get([position / wavelength, seconds * speed]);
+                    (value * 0.5 + 0.5).clamp(0., 1.)
+                }
+                EffectKind::Strobe => {
+                    let rate = f64::from(settings.rate);
+                    if rate > 0. && (seconds * rate).fract() < 0.5 {
+                        1.
+                    } else {
+                        0.
+                    }
+                }
+            };
+            p.set_effect(level.to_f32().unwrap() * settings.level);
+        }
+    }
+}
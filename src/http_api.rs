@@ -0,0 +1,324 @@
+use std::{
+    io::Read,
+    net::{IpAddr, SocketAddr},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use strum::IntoEnumIterator;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+use tungstenite::{handshake::derive_accept_key, protocol::Role, Message, WebSocket};
+
+use crate::{
+    particles::Particle,
+    settings::EaseStyle,
+    tether::{decode_light_message, LightMessages, PayloadEncoding},
+};
+
+/// How long a request waits for the main loop to handle it
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The control panel, served at `/`
+const CONTROL_PANEL: &str = include_str!("../web/index.html");
+
+/// Minimum time between updates sent to stream clients (~30fps)
+const STREAM_INTERVAL: Duration = Duration::from_millis(33);
+
+/// Updates that can wait for a stream client before it counts as too slow
+/// and is dropped
+const STREAM_BACKLOG: usize = 8;
+
+/// Largest request body (bytes) accepted
+const MAX_BODY: usize = 64 * 1024;
+
+/// A request from the HTTP server thread, to be handled in the main loop
+pub enum ApiCommand {
+    /// Goes through the same path as messages from Tether
+    Message(Box<LightMessages>),
+    Status,
+}
+
+pub struct ApiRequest {
+    pub command: ApiCommand,
+    pub reply: mpsc::Sender<ApiResponse>,
+}
+
+pub struct ApiResponse {
+    pub status: u16,
+    /// Always JSON
+    pub body: String,
+}
+
+impl ApiResponse {
+    pub fn ok<T: Serialize>(data: &T) -> Self {
+        match serde_json::to_string(data) {
+            Ok(body) => ApiResponse { status: 200, body },
+            Err(e) => ApiResponse::error(500, &format!("failed to encode response: {e}")),
+        }
+    }
+
+    pub fn error(status: u16, reason: &str) -> Self {
+        ApiResponse {
+            status,
+            body: serde_json::json!({ "error": reason }).to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FixtureState {
+    pub id: usize,
+    pub order: usize,
+    pub brightness: f32,
+    pub stage: &'static str,
+    /// Animation layers playing over the main envelope
+    pub layers: usize,
+}
+
+pub fn fixture_states(particles: &[Particle]) -> Vec<FixtureState> {
+    particles
+        .iter()
+        .map(|p| FixtureState {
+            id: p.id,
+            order: p.order,
+            brightness: p.output_brightness(),
+            stage: p.animation.name(),
+            layers: p.layers().len(),
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiStatus {
+    pub fixtures: Vec<FixtureState>,
+    pub scheduled_messages: usize,
+    pub tether_connected: bool,
+    pub osc_enabled: bool,
+}
+
+/// Embedded HTTP server offering a REST API that mirrors the Tether plugs,
+/// plus a WebSocket stream of live fixture brightness:
+/// - `GET /` (the web control panel)
+/// - `POST /api/trigger`, `/api/trigger/batch`, `/api/trigger/position`, `/api/reset`
+/// - `POST /api/effects`
+/// - `GET`/`PATCH /api/settings`, `POST /api/settings/save`, `/api/settings/load`
+/// - `POST /api/cues`
+/// - `GET /api/status`, `GET /api/styles`
+/// - `GET /api/stream` (WebSocket)
+pub struct HttpApi {
+    requests: mpsc::Receiver<ApiRequest>,
+    stream_clients: Arc<Mutex<Vec<mpsc::SyncSender<String>>>>,
+    last_streamed: Instant,
+}
+
+impl HttpApi {
+    pub fn start(host: IpAddr, port: u16) -> Result<Self, String> {
+        let server = Server::http(SocketAddr::new(host, port)).map_err(|e| e.to_string())?;
+        info!("HTTP API listening on http://{host}:{port}/api");
+
+        let (sender, requests) = mpsc::channel();
+        let stream_clients = Arc::new(Mutex::new(Vec::new()));
+        let clients = stream_clients.clone();
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                handle_request(request, &sender, &clients);
+            }
+        });
+
+        Ok(HttpApi {
+            requests,
+            stream_clients,
+            last_streamed: Instant::now(),
+        })
+    }
+
+    /// Requests received since the last check; each must be replied to
+    pub fn take_requests(&self) -> Vec<ApiRequest> {
+        self.requests.try_iter().collect()
+    }
+
+    /// Send the current state of the fixtures to every stream client,
+    /// dropping any that have disconnected or can't keep up
+    pub fn stream(&mut self, particles: &[Particle]) {
+        if self.last_streamed.elapsed() < STREAM_INTERVAL {
+            return;
+        }
+        let mut clients = self.stream_clients.lock().unwrap();
+        if clients.is_empty() {
+            return;
+        }
+        self.last_streamed = Instant::now();
+        let text = serde_json::json!({ "fixtures": fixture_states(particles) }).to_string();
+        clients.retain(|client| match client.try_send(text.clone()) {
+            Ok(()) => true,
+            Err(mpsc::TrySendError::Full(_)) => {
+                warn!("Dropped a stream client that can't keep up");
+                false
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => false,
+        });
+    }
+}
+
+fn handle_request(
+    mut request: Request,
+    sender: &mpsc::Sender<ApiRequest>,
+    stream_clients: &Arc<Mutex<Vec<mpsc::SyncSender<String>>>>,
+) {
+    let path = request
+        .url()
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .to_owned();
+    let method = request.method().clone();
+    debug!("HTTP {method} {path}");
+
+    if path == "/api/stream" {
+        start_stream(request, stream_clients);
+        return;
+    }
+
+    // These don't need anything from the main loop
+    match (&method, path.as_str()) {
+        (Method::Get, "/") => {
+            let content_type =
+                Header::from_bytes("Content-Type", "text/html; charset=utf-8").unwrap();
+            let response = Response::from_string(CONTROL_PANEL).with_header(content_type);
+            if let Err(e) = request.respond(response) {
+                warn!("Failed to send HTTP response: {e}");
+            }
+            return;
+        }
+        (Method::Get, "/api/styles") => {
+            let styles: Vec<String> = EaseStyle::iter().map(|s| s.to_string()).collect();
+            respond(request, ApiResponse::ok(&styles));
+            return;
+        }
+        _ => {}
+    }
+
+    let plug_name = match (&method, path.as_str()) {
+        (Method::Post, "/api/trigger") => Some("lightTriggers"),
+        (Method::Post, "/api/trigger/batch") => Some("lightBatchTriggers"),
+        (Method::Post, "/api/trigger/position") => Some("lightPositionTriggers"),
+        (Method::Post, "/api/reset") => Some("lightReset"),
+        (Method::Post, "/api/effects") => Some("lightEffects"),
+        (Method::Get, "/api/settings") => Some("settingsGet"),
+        (Method::Patch, "/api/settings") => Some("settingsPatch"),
+        (Method::Post, "/api/settings/save") => Some("settingsSave"),
+        (Method::Post, "/api/settings/load") => Some("settingsLoad"),
+        (Method::Post, "/api/settings/profile") => Some("settingsProfile"),
+        (Method::Post, "/api/cues") => Some("cues"),
+        (Method::Post, "/api/scenes") => Some("scenes"),
+        (Method::Get, "/api/status") => None,
+        _ => {
+            respond(request, ApiResponse::error(404, "not found"));
+            return;
+        }
+    };
+
+    let command = match plug_name {
+        Some(plug_name) => {
+            let too_large =
+                || ApiResponse::error(413, &format!("request body is over {MAX_BODY} bytes"));
+            if request
+                .body_length()
+                .is_some_and(|length| length > MAX_BODY)
+            {
+                respond(request, too_large());
+                return;
+            }
+            let mut body = Vec::new();
+            if let Err(e) = request
+                .as_reader()
+                .take(MAX_BODY as u64 + 1)
+                .read_to_end(&mut body)
+            {
+                respond(request, ApiResponse::error(400, &e.to_string()));
+                return;
+            }
+            if body.len() > MAX_BODY {
+                respond(request, too_large());
+                return;
+            }
+            match decode_light_message(plug_name, &body, PayloadEncoding::Json) {
+                Ok(Some(light_message)) => ApiCommand::Message(Box::new(light_message)),
+                Ok(None) => unreachable!("every route maps to a known plug"),
+                Err(reason) => {
+                    respond(request, ApiResponse::error(400, &reason));
+                    return;
+                }
+            }
+        }
+        None => ApiCommand::Status,
+    };
+
+    let (reply, response) = mpsc::channel();
+    let response = match sender.send(ApiRequest { command, reply }) {
+        Ok(()) => response
+            .recv_timeout(REPLY_TIMEOUT)
+            .unwrap_or_else(|_| ApiResponse::error(503, "no reply from the main loop")),
+        Err(_) => ApiResponse::error(503, "main loop has stopped"),
+    };
+    respond(request, response);
+}
+
+fn respond(request: Request, response: ApiResponse) {
+    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let response = Response::from_string(response.body)
+        .with_status_code(StatusCode(response.status))
+        .with_header(content_type);
+    if let Err(e) = request.respond(response) {
+        warn!("Failed to send HTTP response: {e}");
+    }
+}
+
+/// Upgrade the request to a WebSocket, then send it every update
+/// from `HttpApi::stream` until the client goes away
+fn start_stream(request: Request, stream_clients: &Arc<Mutex<Vec<mpsc::SyncSender<String>>>>) {
+    let key = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+        .map(|h| h.value.to_string());
+    let key = match key {
+        Some(key) => key,
+        None => {
+            respond(
+                request,
+                ApiResponse::error(400, "expected a WebSocket upgrade"),
+            );
+            return;
+        }
+    };
+
+    let response = Response::new_empty(StatusCode(101))
+        .with_header(Header::from_bytes("Upgrade", "websocket").unwrap())
+        .with_header(Header::from_bytes("Connection", "Upgrade").unwrap())
+        .with_header(
+            Header::from_bytes("Sec-WebSocket-Accept", derive_accept_key(key.as_bytes())).unwrap(),
+        );
+    let stream = request.upgrade("websocket", response);
+
+    let (sender, updates) = mpsc::sync_channel::<String>(STREAM_BACKLOG);
+    stream_clients.lock().unwrap().push(sender);
+    thread::spawn(move || {
+        let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+        info!("Stream client connected");
+        for text in updates {
+            if let Err(e) = socket.send(Message::Text(text)) {
+                debug!("Stream client went away: {e}");
+                break;
+            }
+        }
+        if let Err(e) = socket.close(None) {
+            error!("Failed to close stream: {e}");
+        }
+    });
+}
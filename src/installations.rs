@@ -0,0 +1,136 @@
+use std::mem;
+
+use log::info;
+
+use crate::{
+    ambient::Ambient,
+    debounce::Debouncer,
+    effects::Effects,
+    particles::{build_layout, Particle},
+    physics::Physics,
+    settings::{Model, DEFAULT_COUNT},
+    ActivationTarget,
+};
+
+/// The state of one installation's fixtures, while another installation's
+/// state is in the model
+#[derive(Default)]
+pub struct Installation {
+    particles: Vec<Particle>,
+    debouncer: Debouncer<ActivationTarget>,
+    ambient: Ambient,
+    effects: Effects,
+    physics: Physics,
+}
+
+/// Independent installations run by one process, one per Tether group id.
+/// Each has its own fixtures (envelopes, debounce, ambient, effects and
+/// physics), Art-Net universe and Tether output topics, while settings,
+/// opening hours and the tempo clock are shared. The model holds the state
+/// of the active installation, so the rest of the engine doesn't need to
+/// know about installations; `Model::select_installation` swaps it.
+pub struct Installations {
+    /// Group ids, in the order given on the command line; None if there is
+    /// only one installation, which uses the agent's own id
+    ids: Vec<Option<String>>,
+    /// Whose state is in the model
+    active: usize,
+    /// Shown in the window, and driven by the local inputs (mouse, settings
+    /// window, OSC, HTTP, recordings, audio and cues)
+    pub selected: usize,
+    /// The state of every installation but the active one
+    parked: Vec<Option<Installation>>,
+}
+
+impl Installations {
+    /// One installation per group id, if there are several and none of them
+    /// is the "+" wildcard; otherwise a single installation
+    pub fn new(group_ids: &[String]) -> Self {
+        let ids: Vec<Option<String>> =
+            if group_ids.len() > 1 && !group_ids.iter().any(|id| id == "+") {
+                info!("Running an installation for each of {group_ids:?}");
+                group_ids.iter().map(|id| Some(id.clone())).collect()
+            } else {
+                vec![None]
+            };
+        // The first installation starts out active, in the model
+        let parked = (0..ids.len())
+            .map(|i| (i > 0).then(Installation::default))
+            .collect();
+        Installations {
+            ids,
+            parked,
+            active: 0,
+            selected: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// The group id of the installation, if there are several
+    pub fn id(&self, index: usize) -> Option<&str> {
+        self.ids.get(index).and_then(|id| id.as_deref())
+    }
+
+    /// The installation a message from this group id belongs to
+    pub fn index_of(&self, group_id: &str) -> Option<usize> {
+        match self.ids.as_slice() {
+            [None] => Some(0),
+            ids => ids.iter().position(|id| id.as_deref() == Some(group_id)),
+        }
+    }
+
+    /// The installation whose state is in the model
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// The Art-Net universe the active installation is sent on
+    pub fn universe(&self) -> u8 {
+        self.active.try_into().unwrap_or(u8::MAX)
+    }
+
+    /// Every group id, for publishing to all the installations
+    pub fn group_ids(&self) -> Vec<String> {
+        self.ids.iter().flatten().cloned().collect()
+    }
+
+    /// Lay out the fixtures of every installation but the active one, to
+    /// match the layout just built for the model
+    pub fn lay_out_parked(
+        &mut self,
+        count: usize,
+        width: f32,
+        height: f32,
+        order: &[usize; DEFAULT_COUNT],
+    ) {
+        for installation in self.parked.iter_mut().flatten() {
+            installation.particles = build_layout(count, width, height, order);
+        }
+    }
+}
+
+impl Model {
+    /// Put the state of another installation in the model, parking the
+    /// current one
+    pub fn select_installation(&mut self, index: usize) {
+        let active = self.installations.active;
+        if index == active || index >= self.installations.len() {
+            return;
+        }
+        let incoming = self.installations.parked[index]
+            .take()
+            .expect("the active installation is never parked");
+        let outgoing = Installation {
+            particles: mem::replace(&mut self.particles, incoming.particles),
+            debouncer: mem::replace(&mut self.debouncer, incoming.debouncer),
+            ambient: mem::replace(&mut self.ambient, incoming.ambient),
+            effects: mem::replace(&mut self.effects, incoming.effects),
+            physics: mem::replace(&mut self.physics, incoming.physics),
+        };
+        self.installations.parked[active] = Some(outgoing);
+        self.installations.active = index;
+    }
+}
@@ -0,0 +1,65 @@
+use nannou::{
+    prelude::ToPrimitive,
+    rand::rand::{rngs::StdRng, Rng},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::Activation;
+
+/// Random variation applied to each fixture's activation, so that fixtures
+/// triggered together don't all move in lockstep. Every range is ± around
+/// the value it varies; 0 turns that variation off.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct JitterSettings {
+    /// Fraction (0-1) of the attack duration
+    pub attack_duration: f32,
+    /// Fraction (0-1) of the release duration
+    pub release_duration: f32,
+    /// Added to the target brightness, which stays within 0-1
+    pub brightness: f32,
+    /// Time (ms) added to the transmission delay of each neighbour
+    pub transmission_delay: i64,
+}
+
+impl JitterSettings {
+    /// The activation with its attack and release durations varied
+    pub fn durations(&self, rng: &mut StdRng, activation: &Activation) -> Activation {
+        let mut varied = *activation;
+        varied.attack.duration =
+            vary_duration(rng, activation.attack.duration, self.attack_duration);
+        varied.release_duration =
+            vary_duration(rng, activation.release_duration, self.release_duration);
+        varied
+    }
+
+    pub fn brightness(&self, rng: &mut StdRng, brightness: f32) -> f32 {
+        if self.brightness > 0. {
+            (brightness + rng.gen_range(-self.brightness..=self.brightness)).clamp(0., 1.)
+        } else {
+            brightness
+        }
+    }
+
+    /// A transmission delay (ms) varied, but never before the activation itself
+    pub fn transmission_delay(&self, rng: &mut StdRng, delay: i64) -> i64 {
+        if self.transmission_delay > 0 {
+            (delay + rng.gen_range(-self.transmission_delay..=self.transmission_delay)).max(0)
+        } else {
+            delay
+        }
+    }
+}
+
+fn vary_duration(rng: &mut StdRng, duration: usize, fraction: f32) -> usize {
+    if fraction > 0. {
+        let factor = 1. + rng.gen_range(-fraction..=fraction);
+        (duration.to_f32().unwrap() * factor)
+            .round()
+            .max(1.)
+            .to_usize()
+            .unwrap()
+    } else {
+        duration
+    }
+}
@@ -1,17 +1,46 @@
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
 use clap::Parser;
 use env_logger::{Builder, Env};
-use log::{debug, info, warn};
+use log::{debug, error, info, warn};
 use nannou::prelude::*;
+use nannou::rand::rand::rngs::StdRng;
 use nannou_egui::Egui;
-use settings::{Cli, EaseStyle, PhaseSettings, DEFAULT_WINDOW_H, DEFAULT_WINDOW_W};
-use settings::{Model, TransmissionSettings};
-use tether::LightMessages;
+use settings::{
+    fixture_array_to_string, Cli, EaseStyle, PhaseSettings, DEFAULT_HEIGHT_RATIO,
+    DEFAULT_WIDTH_RATIO, DEFAULT_WINDOW_H, DEFAULT_WINDOW_W,
+};
+use settings::{Model, Settings, TransmissionSettings};
+use tether::{
+    CueCommand, CueOperation, EffectCommand, LightEffectMessage, LightMessages,
+    LightTriggerMessage, PositionMode, PositionUnits, ProfileCommand, ProfileOperation,
+    SceneCommand, SceneOperation, ScheduleOptions, SettingsCommand, TriggerOptions,
+};
 use ui::build_ui;
 
+mod ambient;
+
+mod audio;
+use crate::audio::PendingAudio;
+
 mod animation;
+
+mod effects;
+
+mod debounce;
+
+mod jitter;
+use crate::jitter::JitterSettings;
+
+mod merge;
+use crate::debounce::{DebounceSettings, Verdict};
+use crate::merge::{merge_target, MergeSettings};
+
+mod opening_hours;
+
+mod physics;
 use crate::animation::*;
+use crate::opening_hours::OpeningState;
 
 mod settings;
 mod ui;
@@ -24,8 +53,72 @@ mod artnet;
 
 mod tether;
 
+mod osc;
+
+mod installations;
+
+mod http_api;
+use crate::http_api::{fixture_states, ApiCommand, ApiRequest, ApiResponse, ApiStatus};
+
+mod scheduler;
+
+mod recording;
+
+mod sequencer;
+
+mod tempo;
+use crate::tempo::{beats_to_ms, Quantise};
+
+mod scenes;
+
+mod profiles;
+use crate::scheduler::{due_time, Due, ExpiredPolicy, MessageSource, SchedulingSettings};
+
+/// Time (ms) between engine updates when running headless, i.e. about 60fps
+/// like the window
+const HEADLESS_FRAME_INTERVAL: u64 = 16;
+
 fn main() {
-    nannou::app(model).update(update).run();
+    let cli = Cli::parse();
+    if cli.headless {
+        run_headless(&cli);
+    } else {
+        nannou::app(model).update(update).run();
+    }
+}
+
+/// Check the arguments and set up logging, with or without a window
+fn start(cli: &Cli) {
+    cli.check();
+
+    // Initialize the logger from the environment
+    // env_logger::Builder::from_env(Env::default().default_filter_or(&cli.log_level)).init();
+    // env_logger::init();
+
+    let mut builder = Builder::from_env(Env::default().default_filter_or(&cli.log_level));
+    builder.filter_module("wgpu_core", log::LevelFilter::Error);
+    builder.filter_module("wgpu_hal", log::LevelFilter::Warn);
+    builder.filter_module("naga", log::LevelFilter::Warn);
+    builder.init();
+    info!("Started; args: {:?}", cli);
+    debug!("Debugging is enabled; could be verbose");
+}
+
+/// Run the engine without a window (so no display is needed) until the
+/// process is stopped, stepping it on a timer instead of nannou's frames
+fn run_headless(cli: &Cli) {
+    start(cli);
+    info!("Running headless; stop with Ctrl+C");
+    let mut model = Model::defaults(None, None, cli);
+    let interval = Duration::from_millis(HEADLESS_FRAME_INTERVAL);
+    let mut last_step = Instant::now();
+    loop {
+        std::thread::sleep(interval.saturating_sub(last_step.elapsed()));
+        // Whole ms only; the remainder is carried over to the next step
+        let delta_time = last_step.elapsed().as_millis();
+        last_step += Duration::from_millis(delta_time.to_u64().unwrap());
+        step(&mut model, delta_time.to_usize().unwrap());
+    }
 }
 
 // ---------------- Event Handlers
@@ -36,13 +129,10 @@ fn mouse_pressed(_app: &App, model: &mut Model, _button: MouseButton) {
         warn!("mouse click ignored; mouse control disabled");
         return;
     }
-    let PhaseSettings { style, .. } = &model.settings.attack_settings;
-    let attack_duration = model.settings.attack_settings.duration;
-    let release_duration = model.settings.release_settings.duration;
-    let TransmissionSettings {
-        max_range,
-        max_delay,
-    } = &model.settings.transmission_settings;
+    if !model.opening_hours.is_open() {
+        warn!("mouse click ignored; outside opening hours");
+        return;
+    }
 
     let particles = &mut model.particles;
 
@@ -52,33 +142,53 @@ fn mouse_pressed(_app: &App, model: &mut Model, _button: MouseButton) {
         let right = p.position.x + tolerance;
         model.mouse_position.x >= left && model.mouse_position.x <= right
     }) {
-        let id = target_particle.id;
-        let position = target_particle.position;
-        let max_range_pixels = *max_range * DEFAULT_WINDOW_W.to_f32().unwrap();
-        trigger_activation(
-            particles,
-            id,
-            position,
-            model.settings.mouse_brightness_value,
-            model.settings.resting_brightness,
-            attack_duration,
-            release_duration,
-            max_range_pixels,
-            *max_delay,
-            style,
-        );
+        // Recorded as the equivalent remote trigger, so it can be played back
+        let remote_id = if model.settings.trigger_by_order {
+            target_particle.order
+        } else {
+            target_particle.id
+        };
+        let target = ActivationTarget {
+            id: Some(target_particle.id),
+            position: target_particle.position,
+            activation: Activation::new(
+                &model.settings,
+                model.settings.mouse_brightness_value,
+                &TriggerOptions::default(),
+            ),
+        };
+        activate_targets(model, vec![target]);
+        model.ambient.notice_activity();
+
+        if let Some(recorder) = &mut model.recorder {
+            let message = LightMessages::Trigger(LightTriggerMessage {
+                id: remote_id,
+                target_brightness: model.settings.mouse_brightness_value,
+                options: TriggerOptions::default(),
+                schedule: ScheduleOptions::default(),
+            });
+            if let Some(payload) = message.payload() {
+                recorder.record(message.plug_name(), payload);
+            }
+        }
     }
 }
 
-fn fade_all(particles: &mut Vec<Particle>, target_brightness: f32, duration: usize) {
+fn fade_all(
+    particles: &mut Vec<Particle>,
+    target_brightness: f32,
+    duration: usize,
+    style: EaseStyle,
+) {
     for p in particles {
         let release = Animation::new(
             duration,
             p.brightness(),
             target_brightness,
-            get_new_tween(&EaseStyle::Linear),
+            get_new_tween(&style),
         );
         p.animation = EnvelopeStage::ReleaseAnimation(release);
+        p.clear_layers();
         debug!(
             "#{} fade to {} over {}ms",
             p.id, target_brightness, duration
@@ -86,77 +196,272 @@ fn fade_all(particles: &mut Vec<Particle>, target_brightness: f32, duration: usi
     }
 }
 
-// TODO: consolidate these options as PhaseSettings, TransmissionSettings, etc.
-// rather than individual args
+/// The envelope and transmission settings for a single activation,
+/// i.e. the defaults from Settings with any overrides from a message applied
+#[derive(Clone, Copy)]
+pub struct Activation {
+    pub brightness: f32,
+    pub final_brightness: f32,
+    pub attack: PhaseSettings,
+    pub release_duration: usize,
+    pub release_style: EaseStyle,
+    pub transmission: TransmissionSettings,
+    /// Wait (ms) before starting the attack, on the target and its neighbours
+    pub delay: i64,
+    pub debounce: DebounceSettings,
+    pub merge: MergeSettings,
+    /// Play the activation of the target itself in a layer of its own
+    pub layer: Option<LayerOptions>,
+    /// Play transmission to neighbours in layers of their own
+    pub transmission_layer: Option<LayerOptions>,
+    /// Hold the attack back until the next step of the tempo clock
+    pub quantise: Quantise,
+}
+
+impl Activation {
+    pub fn new(settings: &Settings, brightness: f32, options: &TriggerOptions) -> Self {
+        let bpm = settings.tempo.bpm;
+        Activation {
+            brightness,
+            final_brightness: options
+                .final_brightness
+                .unwrap_or(settings.resting_brightness),
+            attack: PhaseSettings {
+                duration: options.attack_duration.unwrap_or_else(|| {
+                    options
+                        .attack_beats
+                        .map_or(settings.attack_settings.duration_at(bpm), |beats| {
+                            beats_to_ms(beats, bpm)
+                        })
+                }),
+                style: options
+                    .attack_style
+                    .unwrap_or(settings.attack_settings.style),
+                beats: None,
+            },
+            release_duration: options.release_duration.unwrap_or_else(|| {
+                options
+                    .release_beats
+                    .map_or(settings.release_settings.duration_at(bpm), |beats| {
+                        beats_to_ms(beats, bpm)
+                    })
+            }),
+            release_style: options
+                .release_style
+                .unwrap_or(settings.release_settings.style),
+            transmission: TransmissionSettings {
+                max_range: options
+                    .transmission_range
+                    .unwrap_or(settings.transmission_settings.max_range),
+                max_delay: options
+                    .transmission_delay
+                    .unwrap_or(settings.transmission_settings.max_delay),
+            },
+            delay: 0,
+            debounce: DebounceSettings {
+                window: options.debounce.unwrap_or(settings.debounce.window),
+                policy: options.debounce_policy.unwrap_or(settings.debounce.policy),
+            },
+            merge: MergeSettings {
+                direct: options.merge.unwrap_or(settings.merge.direct),
+                transmitted: options
+                    .transmission_merge
+                    .unwrap_or(settings.merge.transmitted),
+            },
+            layer: options.layer,
+            transmission_layer: options.transmission_layer.or(settings.transmission_layer),
+            quantise: options.quantise.unwrap_or(settings.tempo.quantise),
+        }
+    }
+}
+
+pub struct ActivationTarget {
+    /// The particle to activate directly, if any; without one, energy is
+    /// only injected into the neighbours of `position` by transmission
+    pub id: Option<usize>,
+    /// Where transmission to neighbouring particles starts from
+    pub position: Point2,
+    pub activation: Activation,
+}
+
+/// Activate all the given targets in one go. Targets are never affected by
+/// transmission from each other (so the order does not matter); any other
+/// particle in range is activated by whichever target transmits the most
+/// brightness to it. Whether an activation takes over from the particle's
+/// current animation depends on the merge policy. Each particle's envelope
+/// is varied by the jitter settings.
 fn trigger_activation(
-    particles: &mut Vec<Particle>,
-    main_target_id: usize,
-    main_target_position: Point2,
-    brightness: f32,
-    final_brightness: f32,
-    attack_duration: usize,
-    release_duration: usize,
-    max_range: f32,
-    max_delay: i64,
-    style: &EaseStyle,
+    particles: &mut [Particle],
+    targets: &[ActivationTarget],
+    jitter: &JitterSettings,
+    rng: &mut StdRng,
 ) {
     for p in particles {
-        if p.id == main_target_id {
-            activate_single(
-                p,
-                attack_duration,
-                release_duration,
-                style,
-                p.brightness(),
-                brightness,
-                final_brightness,
-                0,
-            );
+        if let Some(target) = targets.iter().find(|t| t.id == Some(p.id)) {
+            let activation = &jitter.durations(rng, &target.activation);
+            let brightness = jitter.brightness(rng, activation.brightness);
+            if let Some(options) = activation.layer {
+                activate_layer(p, activation, brightness, activation.delay, options);
+                continue;
+            }
+            match merge_target(activation.merge.direct, p, brightness) {
+                Some(brightness) => activate_single(p, activation, brightness, activation.delay),
+                None => debug!("#{} keeps its current animation", p.id),
+            }
         } else {
-            // let distance = main_target_position.distance(p.position);
-            let distance = (main_target_position.x - p.position.x).abs();
-            if distance <= max_range {
-                if let Some(new_brightness_target) =
-                    possibly_activate_by_transmission(p, distance, max_range, brightness)
-                {
-                    activate_single(
+            let strongest = targets
+                .iter()
+                .filter_map(|t| {
+                    let max_range =
+                        t.activation.transmission.max_range * DEFAULT_WINDOW_W.to_f32().unwrap();
+                    // let distance = main_target_position.distance(p.position);
+                    let distance = (t.position.x - p.position.x).abs();
+                    if max_range > 0. && distance <= max_range {
+                        let brightness =
+                            transmitted_brightness(distance, max_range, t.activation.brightness);
+                        Some((t, brightness, distance, max_range))
+                    } else {
+                        None
+                    }
+                })
+                .max_by(|(_, a, _, _), (_, b, _, _)| a.total_cmp(b));
+
+            if let Some((t, transmitted, distance, max_range)) = strongest {
+                let activation = &jitter.durations(rng, &t.activation);
+                let transmitted = jitter.brightness(rng, transmitted);
+                let delay = jitter.transmission_delay(
+                    rng,
+                    map_range(
+                        distance,
+                        0.,
+                        max_range,
+                        0,
+                        activation.transmission.max_delay,
+                    ),
+                );
+                if let Some(options) = activation.transmission_layer {
+                    activate_layer(
                         p,
-                        attack_duration,
-                        release_duration,
-                        style,
-                        p.brightness(),
-                        new_brightness_target,
-                        final_brightness,
-                        map_range(distance, 0., max_range, 0, max_delay),
-                    )
+                        activation,
+                        transmitted,
+                        activation.delay + delay,
+                        options,
+                    );
+                    continue;
                 }
+                let new_brightness_target =
+                    match merge_target(activation.merge.transmitted, p, transmitted) {
+                        Some(brightness) => brightness,
+                        None => continue,
+                    };
+                activate_single(
+                    p,
+                    activation,
+                    new_brightness_target,
+                    activation.delay + delay,
+                )
             }
         }
     }
 }
 
-fn activate_single(
-    p: &mut Particle,
-    attack_duration: usize,
-    release_duration: usize,
-    ease_style: &EaseStyle,
-    start_brightness: f32,
-    target_brightness: f32,
-    final_brightness: f32,
-    delay: i64,
-) {
+/// Apply the debounce policy of each directly-targeted fixture, then
+/// activate whichever targets are left
+fn trigger_debounced(model: &mut Model, targets: Vec<ActivationTarget>) {
+    let mut accepted = Vec::new();
+    for target in targets {
+        let id = match target.id {
+            Some(id) => id,
+            None => {
+                accepted.push(target);
+                continue;
+            }
+        };
+        let debounce = target.activation.debounce;
+        match model.debouncer.check(id, &debounce) {
+            Verdict::Trigger => accepted.push(target),
+            Verdict::Ignore => debug!("#{id} ignore duplicate trigger"),
+            Verdict::Extend => {
+                let activation = &target.activation;
+                let release = PhaseSettings {
+                    duration: activation.release_duration,
+                    style: activation.release_style,
+                    beats: None,
+                };
+                let extended = model.particles.iter_mut().any(|p| {
+                    p.id == id && p.extend(activation.layer, &activation.attack, &release)
+                });
+                if extended {
+                    debug!("#{id} duplicate trigger; extend its envelope");
+                } else {
+                    accepted.push(target);
+                }
+            }
+            Verdict::Hold => {
+                debug!("#{id} hold duplicate trigger until the window ends");
+                let brightness = target.activation.brightness;
+                model
+                    .debouncer
+                    .hold(id, brightness, debounce.window, target);
+            }
+        }
+    }
+    activate_targets(model, accepted);
+}
+
+/// Hold each target back to the next step of the tempo clock, if it is
+/// quantised. In physics mode, push the chimes instead of starting
+/// envelopes: each target gives an impulse to its own chime, or to the one
+/// nearest its position, and the coupling between chimes takes the place of
+/// transmission.
+fn activate_targets(model: &mut Model, mut targets: Vec<ActivationTarget>) {
+    for target in &mut targets {
+        let quantise = target.activation.quantise;
+        target.activation.delay += model.tempo.until_next(&model.settings.tempo, quantise);
+    }
+    if !model.settings.physics.enabled {
+        trigger_activation(
+            &mut model.particles,
+            &targets,
+            &model.settings.jitter,
+            &mut model.jitter_rng,
+        );
+        return;
+    }
+    for target in &targets {
+        let chime = match target.id {
+            Some(id) => model.particles.iter().find(|p| p.id == id),
+            None => model.particles.iter().min_by(|a, b| {
+                let a = (a.position.x - target.position.x).abs();
+                let b = (b.position.x - target.position.x).abs();
+                a.total_cmp(&b)
+            }),
+        };
+        if let Some(p) = chime {
+            model.physics.impulse(
+                &model.settings.physics,
+                p.order,
+                target.activation.brightness,
+                target.activation.delay,
+            );
+        }
+    }
+}
+
+fn activate_single(p: &mut Particle, activation: &Activation, target_brightness: f32, delay: i64) {
     let mut attack = Animation::new(
-        attack_duration,
-        start_brightness,
+        activation.attack.duration,
+        p.brightness(),
         target_brightness,
-        get_new_tween(ease_style),
+        get_new_tween(&activation.attack.style),
     );
     attack.set_elapsed(-delay);
     p.animation = EnvelopeStage::AttackAnimation(
         attack,
         Some(AfterAttack {
-            release_duration,
-            final_brightness,
+            release_duration: activation.release_duration,
+            release_style: activation.release_style,
+            final_brightness: activation.final_brightness,
         }),
     );
     debug!(
@@ -165,19 +470,52 @@ fn activate_single(
     );
 }
 
-fn possibly_activate_by_transmission(
+/// Play an activation in a new layer over the particle's main envelope. The
+/// layer rises from its neutral level and falls back to it before it is
+/// removed, except an override layer, which goes from the brightness showing
+/// now to the final brightness.
+fn activate_layer(
     p: &mut Particle,
-    distance: f32,
-    max_range: f32,
-    feed_in_brightness: f32,
-) -> Option<f32> {
-    let current_brightness = p.brightness();
-    let target_brightness = map_range(distance, 0., max_range, 1., 0.0) * feed_in_brightness;
-    if target_brightness > current_brightness {
-        Some(target_brightness)
-    } else {
-        None
-    }
+    activation: &Activation,
+    target_brightness: f32,
+    delay: i64,
+    options: LayerOptions,
+) {
+    let (start, final_brightness) = match options.blend {
+        BlendMode::Override => (p.brightness(), activation.final_brightness),
+        blend => {
+            let neutral = AnimationLayer::neutral_level(blend);
+            (neutral, neutral)
+        }
+    };
+    let mut attack = Animation::new(
+        activation.attack.duration,
+        start,
+        target_brightness,
+        get_new_tween(&activation.attack.style),
+    );
+    attack.set_elapsed(-delay);
+    p.add_layer(AnimationLayer {
+        envelope: EnvelopeStage::AttackAnimation(
+            attack,
+            Some(AfterAttack {
+                release_duration: activation.release_duration,
+                release_style: activation.release_style,
+                final_brightness,
+            }),
+        ),
+        options,
+        level: start,
+    });
+    debug!(
+        "#{} add {} layer to target_brightness {}",
+        p.id, options.blend, target_brightness
+    );
+}
+
+/// Brightness fed in by a target, fading out towards the edge of its range
+fn transmitted_brightness(distance: f32, max_range: f32, feed_in_brightness: f32) -> f32 {
+    map_range(distance, 0., max_range, 1., 0.0) * feed_in_brightness
 }
 
 fn mouse_moved(_app: &App, model: &mut Model, pos: Point2) {
@@ -186,7 +524,9 @@ fn mouse_moved(_app: &App, model: &mut Model, pos: Point2) {
 
 fn raw_window_event(_app: &App, model: &mut Model, event: &nannou::winit::event::WindowEvent) {
     // Let egui handle things like keyboard and mouse input.
-    model.egui.handle_raw_event(event);
+    if let Some(egui) = &mut model.egui {
+        egui.handle_raw_event(event);
+    }
 }
 
 fn key_pressed(_app: &App, model: &mut Model, key: Key) {
@@ -203,18 +543,7 @@ fn key_pressed(_app: &App, model: &mut Model, key: Key) {
 
 fn model(app: &App) -> Model {
     let cli = Cli::parse();
-
-    // Initialize the logger from the environment
-    // env_logger::Builder::from_env(Env::default().default_filter_or(&cli.log_level)).init();
-    // env_logger::init();
-
-    let mut builder = Builder::from_env(Env::default().default_filter_or(&cli.log_level));
-    builder.filter_module("wgpu_core", log::LevelFilter::Error);
-    builder.filter_module("wgpu_hal", log::LevelFilter::Warn);
-    builder.filter_module("naga", log::LevelFilter::Warn);
-    builder.init();
-    info!("Started; args: {:?}", cli);
-    debug!("Debugging is enabled; could be verbose");
+    start(&cli);
 
     let window_id = app
         .new_window()
@@ -230,13 +559,13 @@ fn model(app: &App) -> Model {
     let window = app.window(window_id).unwrap();
     let egui = Egui::from_window(&window);
 
-    Model::defaults(window_id, egui, &cli)
+    Model::defaults(Some(window_id), Some(egui), &cli)
 }
 
 // ---------------- Update before drawing every frame
 
 fn update(app: &App, model: &mut Model, update: Update) {
-    let window = app.window(model.window_id).unwrap();
+    let window = app.window(model.window_id.unwrap()).unwrap();
 
     build_ui(model, update.since_start, window.rect());
 
@@ -253,128 +582,562 @@ fn update(app: &App, model: &mut Model, update: Update) {
     );
     app.main_window().set_title(&title);
 
-    for p in &mut model.particles {
-        let animation = &mut p.animation;
-        // let current_time = app.duration.since_start.as_millis();
-
-        match animation {
-            EnvelopeStage::AttackAnimation(a, after_release) => {
-                let (brightness, done) = a.get_brightness_and_done(delta_time);
-                // p.set_brightness(brightness);
-                if done {
-                    debug!("#{} end Attack => Release", p.id);
-                    let (duration, final_brightness) = {
-                        if let Some(after) = after_release {
-                            (after.release_duration, after.final_brightness)
-                        } else {
-                            (model.settings.release_settings.duration, 0.)
-                        }
-                    };
-                    p.animation = EnvelopeStage::ReleaseAnimation(Animation::new(
-                        duration,
-                        p.brightness(),
-                        final_brightness,
-                        get_new_tween(&model.settings.release_settings.style),
-                    ))
-                } else {
-                    p.set_brightness(brightness);
-                }
-            }
-            EnvelopeStage::ReleaseAnimation(a) => {
-                let (brightness, done) = a.get_brightness_and_done(delta_time);
-                p.set_brightness(brightness);
-                if done {
-                    debug!("#{} end Release => Idle", p.id);
-                    p.animation = EnvelopeStage::Idle()
+    step(model, delta_time);
+}
+
+/// Move the engine on by `delta_time` ms: handle every input, animate the
+/// fixtures and send them out. Runs every frame, with or without a window.
+fn step(model: &mut Model, delta_time: usize) {
+    model.select_installation(model.installations.selected);
+    model.tempo.update(&model.settings.tempo, delta_time);
+    let opening_change = model
+        .opening_hours
+        .update(&model.settings.opening_hours, delta_time);
+    if model.profiles.update(delta_time, &mut model.settings) {
+        info!(
+            "Finished switching to profile {:?}",
+            model.profiles.active()
+        );
+        model.tether.publish_settings(&model.settings);
+    }
+
+    let send_artnet = model.last_artnet_sent.elapsed().unwrap()
+        > Duration::from_millis(model.settings.artnet_update_interval);
+    if send_artnet {
+        model.last_artnet_sent = std::time::SystemTime::now();
+    }
+    for installation in 0..model.installations.len() {
+        model.select_installation(installation);
+        update_installation(model, delta_time, opening_change, send_artnet);
+    }
+
+    if model.tether.is_connected() {
+        for (light_message, group) in model.tether.check_messages() {
+            match model.installations.index_of(&group) {
+                Some(installation) => {
+                    receive_and_report(model, light_message, MessageSource::Tether, installation)
                 }
+                None => warn!("Ignoring message for unknown group {group}"),
             }
-            EnvelopeStage::Idle() => {}
         }
     }
 
-    if model.last_artnet_sent.elapsed().unwrap()
-        > Duration::from_millis(model.settings.artnet_update_interval)
-    {
-        model.last_artnet_sent = std::time::SystemTime::now();
+    let selected = model.installations.selected;
+    if let Some(osc) = &mut model.osc {
+        for light_message in osc.check_messages() {
+            receive_and_report(model, light_message, MessageSource::Osc, selected);
+        }
+    }
+
+    if let Some(http) = &model.http {
+        for request in http.take_requests() {
+            model.select_installation(selected);
+            handle_api_request(model, request);
+        }
+    }
+
+    if let Some(player) = &mut model.player {
+        for light_message in player.advance(delta_time) {
+            receive_and_report(model, light_message, MessageSource::Recording, selected);
+        }
+    }
+
+    if let Some(result) = model.audio_pending.as_ref().and_then(PendingAudio::poll) {
+        model.audio_pending = None;
+        match result {
+            Ok(player) => model.audio = Some(player),
+            Err(e) => error!("Failed to load audio file: {e}"),
+        }
+    }
+
+    if let Some(audio) = &mut model.audio {
+        for light_message in audio.advance(delta_time, &model.settings.audio) {
+            receive_and_report(model, light_message, MessageSource::Audio, selected);
+        }
+    }
+
+    if let Some(sequencer) = &mut model.sequencer {
+        for light_message in sequencer.advance(delta_time) {
+            receive_and_report(model, light_message, MessageSource::Cue, selected);
+        }
+    }
+
+    for (light_message, source, installation) in model.scheduler.take_due(SystemTime::now()) {
+        model.select_installation(installation);
+        let plug_name = light_message.plug_name();
+        if let Err(reason) = handle_light_message(model, light_message) {
+            report_rejection(model, source, installation, plug_name, &reason);
+        }
+    }
+
+    // Leave the installation shown in the window in the model
+    model.select_installation(selected);
+    if let Some(http) = &mut model.http {
+        http.stream(&model.particles);
+    }
+}
+
+/// Move the active installation's fixtures on by a frame, and send them
+/// over Art-Net if it is time to
+fn update_installation(
+    model: &mut Model,
+    delta_time: usize,
+    opening_change: Option<OpeningState>,
+    send_artnet: bool,
+) {
+    let release_settings = PhaseSettings {
+        duration: model
+            .settings
+            .release_settings
+            .duration_at(model.settings.tempo.bpm),
+        ..model.settings.release_settings
+    };
+    for p in &mut model.particles {
+        p.update(delta_time, &release_settings);
+    }
+
+    let coalesced = model.debouncer.update(delta_time);
+    if !coalesced.is_empty() && model.opening_hours.is_open() {
+        activate_targets(model, coalesced);
+    }
+
+    model
+        .ambient
+        .update(&model.settings.ambient, delta_time, &mut model.particles);
+    model.effects.update(delta_time, &mut model.particles);
+    model
+        .physics
+        .update(&model.settings.physics, delta_time, &mut model.particles);
+
+    let fade_duration = model
+        .settings
+        .opening_hours
+        .fade_duration
+        .to_usize()
+        .unwrap();
+    match opening_change {
+        Some(state) if state.is_open() => fade_all(
+            &mut model.particles,
+            model.settings.resting_brightness,
+            fade_duration,
+            EaseStyle::Linear,
+        ),
+        Some(_) => {
+            fade_all(&mut model.particles, 0., fade_duration, EaseStyle::Linear);
+            model.effects.stop(None);
+        }
+        None => {}
+    }
+
+    let master = model.opening_hours.master();
+    for p in &mut model.particles {
+        p.set_master(master);
+    }
+
+    if send_artnet {
         model.artnet.update(
             &model.particles,
             model.settings.channels_per_pixel,
             model.settings.artnet_high_res,
+            model.installations.universe(),
         );
     }
+}
 
-    if model.tether.is_connected() {
-        let PhaseSettings { style, .. } = &model.settings.attack_settings;
-
-        let trigger_by_order = model.settings.trigger_by_order;
-        let particles = &mut model.particles;
-        if let Some(light_message) = model.tether.check_messages() {
-            match light_message {
-                LightMessages::Trigger(m) => {
-                    if let Some(target_particle) = particles.iter().find(|p| {
-                        m.id == {
-                            if trigger_by_order {
-                                p.order
-                            } else {
-                                p.id
-                            }
-                        }
-                    }) {
-                        let position = target_particle.position;
-                        let id = target_particle.id;
-                        let trigger_brightness = {
-                            if model.settings.trigger_full_brightness {
-                                1.
-                            } else {
-                                m.target_brightness
-                            }
-                        };
-
-                        let max_range = m
-                            .transmission_range
-                            .unwrap_or(model.settings.transmission_settings.max_range);
-                        let max_range_pixels = max_range * DEFAULT_WINDOW_W.to_f32().unwrap();
-
-                        let max_delay = m
-                            .transmission_delay
-                            .unwrap_or(model.settings.transmission_settings.max_delay);
-
-                        let attack_duration = m
-                            .attack_duration
-                            .unwrap_or(model.settings.attack_settings.duration);
-
-                        let release_duration = m
-                            .release_duration
-                            .unwrap_or(model.settings.release_settings.duration);
-
-                        let final_brightness = m
-                            .final_brightness
-                            .unwrap_or(model.settings.resting_brightness);
-
-                        trigger_activation(
-                            particles,
-                            id,
-                            position,
-                            trigger_brightness,
-                            final_brightness,
-                            attack_duration,
-                            release_duration,
-                            max_range_pixels,
-                            max_delay,
-                            style,
+/// API requests go through the same path as Tether messages; settings
+/// commands reply with the resulting settings, as the settings plug does
+fn handle_api_request(model: &mut Model, request: ApiRequest) {
+    let ApiRequest { command, reply } = request;
+    let response = match command {
+        ApiCommand::Message(light_message) => {
+            let is_settings = matches!(*light_message, LightMessages::Settings(_));
+            let is_profile = matches!(
+                *light_message,
+                LightMessages::Settings(SettingsCommand::Profile(_))
+            );
+            match receive_light_message(model, *light_message, MessageSource::Http) {
+                Ok(()) if is_profile => ApiResponse::ok(&model.profiles.list()),
+                Ok(()) if is_settings => ApiResponse::ok(&model.settings),
+                Ok(()) => ApiResponse::ok(&serde_json::json!({ "ok": true })),
+                Err(reason) => ApiResponse::error(400, &reason),
+            }
+        }
+        ApiCommand::Status => ApiResponse::ok(&ApiStatus {
+            fixtures: fixture_states(&model.particles),
+            scheduled_messages: model.scheduler.len(),
+            tether_connected: model.tether.is_connected(),
+            osc_enabled: model.osc.is_some(),
+        }),
+    };
+    // The client may have given up waiting already
+    reply.send(response).ok();
+}
+
+/// Handle a message for one of the installations, reporting any problem back
+fn receive_and_report(
+    model: &mut Model,
+    light_message: LightMessages,
+    source: MessageSource,
+    installation: usize,
+) {
+    model.select_installation(installation);
+    let plug_name = light_message.plug_name();
+    if let Err(reason) = receive_light_message(model, light_message, source) {
+        report_rejection(model, source, installation, plug_name, &reason);
+    }
+}
+
+/// Report a problem with a message via whichever route it came in on. HTTP
+/// requests get their errors in the response; this only covers the ones
+/// that were scheduled, whose response has been sent already.
+fn report_rejection(
+    model: &Model,
+    source: MessageSource,
+    installation: usize,
+    plug_name: &str,
+    reason: &str,
+) {
+    match source {
+        MessageSource::Tether => {
+            let group = model.installations.id(installation);
+            model.tether.publish_error(plug_name, reason, group);
+        }
+        MessageSource::Audio => debug!("Rejected audio trigger for {plug_name}: {reason}"),
+        _ => warn!("Rejected {source} message for {plug_name}: {reason}"),
+    }
+}
+
+/// Run a newly-received message now, or queue it if it is scheduled for later.
+/// Returns the reason if the message is rejected, so that it can be reported
+/// back via whichever route it came in on.
+fn receive_light_message(
+    model: &mut Model,
+    light_message: LightMessages,
+    source: MessageSource,
+) -> Result<(), String> {
+    let (execute_at, execute_in) = match light_message.schedule() {
+        Some(schedule) => (schedule.execute_at, schedule.execute_in),
+        None => (None, None),
+    };
+    match due_time(execute_at, execute_in, SystemTime::now()) {
+        Due::Now => handle_light_message(model, light_message),
+        Due::Later(due) => {
+            let installation = model.installations.active();
+            model
+                .scheduler
+                .schedule(due, light_message, source, installation);
+            Ok(())
+        }
+        Due::Late(late_by) => {
+            let SchedulingSettings {
+                late_tolerance,
+                expired_policy,
+            } = model.settings.scheduling;
+            if late_by <= late_tolerance {
+                debug!("Message arrived {late_by}ms late; within tolerance");
+                handle_light_message(model, light_message)
+            } else {
+                match expired_policy {
+                    ExpiredPolicy::Fire => {
+                        report_rejection(
+                            model,
+                            source,
+                            model.installations.active(),
+                            light_message.plug_name(),
+                            &format!("message arrived {late_by}ms late; executed anyway"),
                         );
+                        handle_light_message(model, light_message)
+                    }
+                    ExpiredPolicy::Drop => {
+                        Err(format!("message arrived {late_by}ms late; dropped"))
                     }
                 }
-                LightMessages::Reset(m) => fade_all(
-                    particles,
-                    m.target_brightness.unwrap_or(0.),
-                    m.fade_duration.unwrap_or(0),
+            }
+        }
+    }
+}
+
+/// Apply a message, recording it if it is accepted
+fn handle_light_message(model: &mut Model, light_message: LightMessages) -> Result<(), String> {
+    // Taken beforehand, since applying the message consumes it
+    let payload = model
+        .recorder
+        .as_ref()
+        .and_then(|_| light_message.payload());
+    let plug_name = light_message.plug_name();
+    let is_activity = !matches!(
+        light_message,
+        LightMessages::Settings(_) | LightMessages::Cue(_)
+    );
+    apply_light_message(model, light_message)?;
+    // Only once it has run, so that rejected messages don't hold off the ambient layer
+    if is_activity {
+        model.ambient.notice_activity();
+    }
+    if let (Some(recorder), Some(payload)) = (&mut model.recorder, payload) {
+        recorder.record(plug_name, payload);
+    }
+    Ok(())
+}
+
+fn apply_light_message(model: &mut Model, light_message: LightMessages) -> Result<(), String> {
+    let is_trigger = matches!(
+        light_message,
+        LightMessages::Trigger(_)
+            | LightMessages::BatchTrigger(_)
+            | LightMessages::PositionTrigger(_)
+            | LightMessages::Effect(LightEffectMessage {
+                command: EffectCommand::Start,
+                ..
+            })
+    );
+    if is_trigger && !model.opening_hours.is_open() {
+        return Err(format!("ignored; {}", model.opening_hours.state()));
+    }
+    match light_message {
+        LightMessages::Trigger(m) => {
+            let (id, position) =
+                find_fixture(model, m.id).ok_or_else(|| unknown_fixture_reason(model, m.id))?;
+            let target = ActivationTarget {
+                id: Some(id),
+                position,
+                activation: Activation::new(
+                    &model.settings,
+                    remote_trigger_brightness(&model.settings, m.target_brightness),
+                    &m.options,
                 ),
+            };
+            trigger_debounced(model, vec![target]);
+        }
+        LightMessages::BatchTrigger(m) => {
+            let mut targets = Vec::new();
+            for entry in m.entries(&model.settings.fixture_groups)? {
+                // Reject the whole batch, rather than playing only part of it
+                let (id, position) = find_fixture(model, entry.id)
+                    .ok_or_else(|| unknown_fixture_reason(model, entry.id))?;
+                let brightness = entry.target_brightness.unwrap_or(m.target_brightness);
+                let mut activation = Activation::new(
+                    &model.settings,
+                    remote_trigger_brightness(&model.settings, brightness),
+                    &m.options,
+                );
+                activation.delay = entry.delay.unwrap_or(0);
+                targets.push(ActivationTarget {
+                    id: Some(id),
+                    position,
+                    activation,
+                });
+            }
+            trigger_debounced(model, targets);
+        }
+        LightMessages::PositionTrigger(m) => {
+            let position = Point2::new(row_position_to_x(&model.settings, m.x, m.units), 0.);
+            let id = match m.mode {
+                PositionMode::Nearest => model
+                    .particles
+                    .iter()
+                    .min_by(|a, b| {
+                        (a.position.x - position.x)
+                            .abs()
+                            .total_cmp(&(b.position.x - position.x).abs())
+                    })
+                    .map(|p| p.id),
+                PositionMode::Point => None,
+            };
+            let target = ActivationTarget {
+                id,
+                position,
+                activation: Activation::new(
+                    &model.settings,
+                    remote_trigger_brightness(&model.settings, m.target_brightness),
+                    &m.options,
+                ),
+            };
+            trigger_debounced(model, vec![target]);
+        }
+        LightMessages::Reset(m) => fade_all(
+            &mut model.particles,
+            m.target_brightness.unwrap_or(0.),
+            m.fade_duration.unwrap_or(0),
+            m.fade_style.unwrap_or(EaseStyle::Linear),
+        ),
+        LightMessages::Effect(m) => match (m.command, m.effect) {
+            (EffectCommand::Start, Some(effect)) => {
+                model
+                    .effects
+                    .start(effect, &model.settings.effects, &m.params)
+            }
+            (EffectCommand::Start, None) => return Err(String::from("start needs an effect")),
+            (EffectCommand::Stop, effect) => model.effects.stop(effect),
+        },
+        LightMessages::Settings(command) => return handle_settings_command(model, command),
+        LightMessages::Cue(command) => return handle_cue_command(model, command),
+        LightMessages::Scene(command) => return handle_scene_command(model, command),
+    }
+    Ok(())
+}
+
+/// Find the (id, position) of the fixture a remote message refers to,
+/// which depends on whether remote triggers are by order or by #ID
+fn find_fixture(model: &Model, remote_id: usize) -> Option<(usize, Point2)> {
+    let trigger_by_order = model.settings.trigger_by_order;
+    model
+        .particles
+        .iter()
+        .find(|p| {
+            remote_id == {
+                if trigger_by_order {
+                    p.order
+                } else {
+                    p.id
+                }
             }
+        })
+        .map(|p| (p.id, p.position))
+}
+
+fn unknown_fixture_reason(model: &Model, remote_id: usize) -> String {
+    format!(
+        "no fixture with {} {}",
+        if model.settings.trigger_by_order {
+            "order"
+        } else {
+            "id"
+        },
+        remote_id
+    )
+}
+
+/// Convert a position along the row of fixtures (from the left end) into
+/// an x coordinate on screen, where transmission distances are measured
+fn row_position_to_x(settings: &Settings, along: f32, units: PositionUnits) -> f32 {
+    let row_width = DEFAULT_WINDOW_W.to_f32().unwrap() * DEFAULT_WIDTH_RATIO;
+    let normalised = match units {
+        PositionUnits::Normalised => along,
+        PositionUnits::Metres => along / settings.physical_row_width,
+    };
+    -row_width / 2. + normalised * row_width
+}
+
+fn remote_trigger_brightness(settings: &Settings, requested: f32) -> f32 {
+    if settings.trigger_full_brightness {
+        1.
+    } else {
+        requested
+    }
+}
+
+fn handle_cue_command(model: &mut Model, command: CueCommand) -> Result<(), String> {
+    let sequencer = model.sequencer.as_mut().ok_or("no cue list loaded")?;
+    let cue = command
+        .cue
+        .as_ref()
+        .map(|cue| sequencer.find(cue))
+        .transpose()?;
+    match (command.command, cue) {
+        (CueOperation::Go, Some(cue)) => sequencer.go_to(cue),
+        (CueOperation::Go, None) => sequencer.go()?,
+        (CueOperation::Jump, Some(cue)) => sequencer.jump(cue),
+        (CueOperation::Jump, None) => return Err(String::from("jump needs a cue")),
+        (CueOperation::Stop, _) => sequencer.stop(),
+        (CueOperation::Pause, _) => sequencer.pause(),
+        (CueOperation::Resume, _) => sequencer.resume(),
+    }
+    Ok(())
+}
+
+fn handle_scene_command(model: &mut Model, command: SceneCommand) -> Result<(), String> {
+    match command.command {
+        SceneOperation::Recall => {
+            let scene = model.scenes.find(&command.name)?;
+            let duration = command
+                .fade_duration
+                .unwrap_or(model.settings.scene_fade_duration);
+            let style = command
+                .fade_style
+                .unwrap_or(model.settings.scene_fade_style);
+            info!("Recall scene \"{}\" over {duration}ms", scene.name);
+            scene.recall(&mut model.particles, duration, style);
+            Ok(())
         }
+        SceneOperation::Capture => model.scenes.capture(&command.name, &model.particles),
+        SceneOperation::Delete => model.scenes.delete(&command.name),
     }
 }
 
+/// Settings commands always publish the resulting settings, even if the
+/// command itself failed, so that remote controls stay in sync
+fn handle_settings_command(model: &mut Model, command: SettingsCommand) -> Result<(), String> {
+    let previous_order = model.settings.fixture_order;
+    let previous_count = model.settings.chimes_count;
+    let previous_mapping = model.settings.lights_lookup_mapping;
+
+    let result = match command {
+        SettingsCommand::Get => Ok(()),
+        SettingsCommand::Patch(patch) => model.settings.apply_patch(&patch).map(|()| {
+            info!("Applied remote settings patch {patch}");
+        }),
+        SettingsCommand::Save => model
+            .settings
+            .save()
+            .map_err(|()| String::from("failed to save settings file")),
+        SettingsCommand::Load => match model.settings.load() {
+            Ok(()) => {
+                model.settings.fixture_order_string =
+                    fixture_array_to_string(&model.settings.fixture_order);
+                Ok(())
+            }
+            Err(()) => Err(String::from("failed to load settings file")),
+        },
+        SettingsCommand::Profile(command) => handle_profile_command(model, command),
+    };
+
+    if model.settings.lights_lookup_mapping != previous_mapping {
+        model
+            .artnet
+            .create_brightness_mapping(&model.settings.lights_lookup_mapping);
+    }
+    if model.settings.fixture_order != previous_order
+        || model.settings.chimes_count != previous_count
+    {
+        let width = DEFAULT_WINDOW_W.to_f32().unwrap() * DEFAULT_WIDTH_RATIO;
+        let height = DEFAULT_WINDOW_H.to_f32().unwrap() * DEFAULT_HEIGHT_RATIO;
+        model.particles = build_layout(
+            model.settings.chimes_count,
+            width,
+            height,
+            &model.settings.fixture_order,
+        );
+        model.installations.lay_out_parked(
+            model.settings.chimes_count,
+            width,
+            height,
+            &model.settings.fixture_order,
+        );
+    }
+
+    model.tether.publish_settings(&model.settings);
+    result
+}
+
+fn handle_profile_command(model: &mut Model, command: ProfileCommand) -> Result<(), String> {
+    let result = match (command.command, command.name) {
+        (ProfileOperation::Switch, Some(name)) => model.profiles.switch(
+            &name,
+            &mut model.settings,
+            command
+                .transition
+                .unwrap_or(model.profiles.default_transition),
+        ),
+        (ProfileOperation::Save, Some(name)) => model.profiles.save(&name, &model.settings),
+        (ProfileOperation::Delete, Some(name)) => model.profiles.delete(&name),
+        (ProfileOperation::List, _) => Ok(()),
+        (_, None) => Err(String::from("name is required, except to list")),
+    };
+    model.tether.publish_profiles(&model.profiles.list());
+    result
+}
+
 // ---------------- Draw every frame
 
 fn view(app: &App, model: &Model, frame: Frame) {
@@ -386,12 +1149,19 @@ fn view(app: &App, model: &Model, frame: Frame) {
         draw.to_frame(app, &frame).unwrap();
     } else {
         for p in &model.particles {
+            // Swings from the top of the chime, in physics mode
+            let angle = model.physics.angle(p.order);
+            let half_length = model.settings.chime_length / 2.;
             draw
                 // .ellipse()
                 .rect()
                 .w_h(model.settings.chime_thickness, model.settings.chime_length)
-                .x_y(p.position.x, p.position.y)
-                .color(gray(p.brightness()));
+                .x_y(
+                    p.position.x + half_length * angle.sin(),
+                    p.position.y + half_length * (1. - angle.cos()),
+                )
+                .rotate(angle)
+                .color(gray(p.output_brightness()));
 
             if model.settings.show_brightness_indicator {
                 let size = model.settings.chime_length / 2.;
@@ -399,7 +1169,7 @@ fn view(app: &App, model: &Model, frame: Frame) {
                     .w_h(model.settings.chime_thickness * 1.25, 2.)
                     .x_y(
                         p.position.x,
-                        p.position.y + map_range(p.brightness(), 0., 1., size, -size),
+                        p.position.y + map_range(p.output_brightness(), 0., 1., size, -size),
                     )
                     .color({
                         match p.animation {
@@ -418,6 +1188,8 @@ fn view(app: &App, model: &Model, frame: Frame) {
             }
         }
         draw.to_frame(app, &frame).unwrap();
-        model.egui.draw_to_frame(&frame).unwrap();
+        if let Some(egui) = &model.egui {
+            egui.draw_to_frame(&frame).unwrap();
+        }
     }
 }
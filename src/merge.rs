@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
+
+use crate::{animation::EnvelopeStage, particles::Particle};
+
+/// What happens when a new activation hits a fixture that is already animating
+#[derive(PartialEq, Clone, Copy, Debug, EnumIter, Display, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MergePolicy {
+    /// The new activation always takes over
+    Replace,
+    /// Highest takes precedence: the new activation only takes over if it
+    /// is brighter than the fixture is showing, or heading to
+    Max,
+    /// Add the new brightness to the fixture's, up to full brightness
+    Add,
+    /// Leave a fixture alone until it has finished animating
+    IgnoreWhileActive,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeSettings {
+    /// For the fixtures triggered directly
+    pub direct: MergePolicy,
+    /// For the neighbours activated by transmission
+    pub transmitted: MergePolicy,
+}
+
+impl Default for MergeSettings {
+    fn default() -> Self {
+        MergeSettings {
+            direct: MergePolicy::Replace,
+            transmitted: MergePolicy::Max,
+        }
+    }
+}
+
+/// The brightness the fixture is heading to if it is in its attack
+/// (including an attack still waiting for a delayed start), or else the
+/// brightness it is showing now
+fn committed_brightness(p: &Particle) -> f32 {
+    match &p.animation {
+        EnvelopeStage::AttackAnimation(attack, _) => {
+            attack.tweener.final_value().max(p.brightness())
+        }
+        EnvelopeStage::ReleaseAnimation(_) | EnvelopeStage::Idle() => p.brightness(),
+    }
+}
+
+/// The brightness to activate the fixture to, or `None` to leave it alone
+pub fn merge_target(policy: MergePolicy, p: &Particle, target_brightness: f32) -> Option<f32> {
+    match policy {
+        MergePolicy::Replace => Some(target_brightness),
+        MergePolicy::Max => {
+            if target_brightness > committed_brightness(p) {
+                Some(target_brightness)
+            } else {
+                None
+            }
+        }
+        MergePolicy::Add => Some((committed_brightness(p) + target_brightness).min(1.)),
+        MergePolicy::IgnoreWhileActive => match p.animation {
+            EnvelopeStage::Idle() => Some(target_brightness),
+            _ => None,
+        },
+    }
+}
@@ -0,0 +1,305 @@
+use std::{fmt, time::Instant};
+
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, Timelike, Weekday};
+use log::info;
+use nannou::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+/// How often to check the wall clock, in ms
+const CHECK_INTERVAL: u128 = 1000;
+
+/// A time of day, written "HH:MM" (24 hour); "24:00" is the end of the day
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ClockTime {
+    /// Minutes since midnight
+    minutes: u32,
+}
+
+impl TryFrom<String> for ClockTime {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid time \"{s}\"; expected HH:MM");
+        let (hours, minutes) = s.split_once(':').ok_or_else(invalid)?;
+        let hours: u32 = hours.trim().parse().map_err(|_| invalid())?;
+        let minutes: u32 = minutes.trim().parse().map_err(|_| invalid())?;
+        // Hours are checked first, so that the total can't overflow
+        if hours > 24 || minutes >= 60 || hours * 60 + minutes > 24 * 60 {
+            return Err(invalid());
+        }
+        Ok(ClockTime {
+            minutes: hours * 60 + minutes,
+        })
+    }
+}
+
+impl From<ClockTime> for String {
+    fn from(time: ClockTime) -> Self {
+        time.to_string()
+    }
+}
+
+impl fmt::Display for ClockTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.minutes / 60, self.minutes % 60)
+    }
+}
+
+/// From one time of day to another; if `to` is earlier than `from`,
+/// the window continues past midnight into the next day
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TimeWindow {
+    pub from: ClockTime,
+    pub to: ClockTime,
+}
+
+impl TimeWindow {
+    /// Whether the part of the window on the day it starts includes `time`
+    fn contains_same_day(&self, time: ClockTime) -> bool {
+        if self.from <= self.to {
+            self.from <= time && time < self.to
+        } else {
+            self.from <= time
+        }
+    }
+
+    /// Whether the part of the window after midnight includes `time`
+    fn contains_next_day(&self, time: ClockTime) -> bool {
+        self.from > self.to && time < self.to
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.from, self.to)
+    }
+}
+
+/// Scale the brightness of everything during part of every day
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DimmingPeriod {
+    pub from: ClockTime,
+    pub to: ClockTime,
+    pub brightness: f32,
+}
+
+/// When the installation is open, for each day of the week
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WeeklyHours {
+    pub mon: Vec<TimeWindow>,
+    pub tue: Vec<TimeWindow>,
+    pub wed: Vec<TimeWindow>,
+    pub thu: Vec<TimeWindow>,
+    pub fri: Vec<TimeWindow>,
+    pub sat: Vec<TimeWindow>,
+    pub sun: Vec<TimeWindow>,
+}
+
+impl WeeklyHours {
+    pub fn for_day(&self, day: Weekday) -> &[TimeWindow] {
+        match day {
+            Weekday::Mon => &self.mon,
+            Weekday::Tue => &self.tue,
+            Weekday::Wed => &self.wed,
+            Weekday::Thu => &self.thu,
+            Weekday::Fri => &self.fri,
+            Weekday::Sat => &self.sat,
+            Weekday::Sun => &self.sun,
+        }
+    }
+}
+
+impl Default for WeeklyHours {
+    fn default() -> Self {
+        let day = || {
+            vec![TimeWindow {
+                from: ClockTime { minutes: 9 * 60 },
+                to: ClockTime { minutes: 18 * 60 },
+            }]
+        };
+        WeeklyHours {
+            mon: day(),
+            tue: day(),
+            wed: day(),
+            thu: day(),
+            fri: day(),
+            sat: day(),
+            sun: day(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OpeningHoursSettings {
+    pub enabled: bool,
+    pub weekdays: WeeklyHours,
+    /// If periods overlap, the dimmest wins
+    pub dimming: Vec<DimmingPeriod>,
+    /// Dates ("YYYY-MM-DD") to stay closed all day
+    pub holidays: Vec<NaiveDate>,
+    /// Time (ms) to fade out when closing, in when opening,
+    /// and between dimming levels
+    pub fade_duration: u64,
+}
+
+impl Default for OpeningHoursSettings {
+    fn default() -> Self {
+        OpeningHoursSettings {
+            enabled: false,
+            weekdays: WeeklyHours::default(),
+            dimming: Vec::new(),
+            holidays: Vec::new(),
+            fade_duration: 5000,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum OpeningState {
+    /// Opening hours are not in use; always open at full brightness
+    Disabled,
+    Open {
+        brightness: f32,
+    },
+    Closed,
+    Holiday,
+}
+
+impl OpeningState {
+    pub fn is_open(&self) -> bool {
+        matches!(self, OpeningState::Disabled | OpeningState::Open { .. })
+    }
+
+    fn brightness(&self) -> f32 {
+        match self {
+            OpeningState::Disabled => 1.,
+            OpeningState::Open { brightness } => *brightness,
+            OpeningState::Closed | OpeningState::Holiday => 0.,
+        }
+    }
+}
+
+impl fmt::Display for OpeningState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpeningState::Disabled => write!(f, "Always open (opening hours disabled)"),
+            OpeningState::Open { brightness } if *brightness < 1. => {
+                write!(f, "Open, dimmed to {:.0}%", brightness * 100.)
+            }
+            OpeningState::Open { .. } => write!(f, "Open"),
+            OpeningState::Closed => write!(f, "Closed"),
+            OpeningState::Holiday => write!(f, "Closed (holiday)"),
+        }
+    }
+}
+
+/// Whether the installation should be open at a given local time, and how bright
+pub fn state_at(settings: &OpeningHoursSettings, now: NaiveDateTime) -> OpeningState {
+    if !settings.enabled {
+        return OpeningState::Disabled;
+    }
+    let today = now.date();
+    if settings.holidays.contains(&today) {
+        return OpeningState::Holiday;
+    }
+
+    let time = ClockTime {
+        minutes: now.hour() * 60 + now.minute(),
+    };
+    let yesterday = today.pred_opt().unwrap_or(today);
+    let open = settings
+        .weekdays
+        .for_day(today.weekday())
+        .iter()
+        .any(|w| w.contains_same_day(time))
+        || (!settings.holidays.contains(&yesterday)
+            && settings
+                .weekdays
+                .for_day(yesterday.weekday())
+                .iter()
+                .any(|w| w.contains_next_day(time)));
+    if !open {
+        return OpeningState::Closed;
+    }
+
+    let brightness = settings
+        .dimming
+        .iter()
+        .filter(|d| {
+            let window = TimeWindow {
+                from: d.from,
+                to: d.to,
+            };
+            window.contains_same_day(time) || window.contains_next_day(time)
+        })
+        .map(|d| d.brightness)
+        .fold(1., f32::min);
+    OpeningState::Open { brightness }
+}
+
+/// Follows the opening hours, fading the master brightness between states
+pub struct OpeningHours {
+    state: OpeningState,
+    master: f32,
+    last_checked: Option<Instant>,
+}
+
+impl Default for OpeningHours {
+    fn default() -> Self {
+        OpeningHours {
+            state: OpeningState::Disabled,
+            master: 1.,
+            last_checked: None,
+        }
+    }
+}
+
+impl OpeningHours {
+    pub fn state(&self) -> OpeningState {
+        self.state
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state.is_open()
+    }
+
+    /// Multiplier for the brightness of every fixture
+    pub fn master(&self) -> f32 {
+        self.master
+    }
+
+    /// Returns the new state if the installation has just opened or closed
+    pub fn update(
+        &mut self,
+        settings: &OpeningHoursSettings,
+        delta_time: usize,
+    ) -> Option<OpeningState> {
+        let mut changed = None;
+        let due = self
+            .last_checked
+            .is_none_or(|checked| checked.elapsed().as_millis() >= CHECK_INTERVAL);
+        if due {
+            self.last_checked = Some(Instant::now());
+            let state = state_at(settings, Local::now().naive_local());
+            if state != self.state {
+                info!("Opening hours: {state}");
+                if state.is_open() != self.state.is_open() {
+                    changed = Some(state);
+                }
+                self.state = state;
+            }
+        }
+
+        let target = self.state.brightness();
+        let step = delta_time.to_f32().unwrap() / settings.fade_duration.max(1).to_f32().unwrap();
+        self.master = if self.master < target {
+            (self.master + step).min(target)
+        } else {
+            (self.master - step).max(target)
+        };
+
+        changed
+    }
+}
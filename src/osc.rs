@@ -0,0 +1,304 @@
+use std::{
+    io,
+    net::{IpAddr, SocketAddr, UdpSocket},
+};
+
+use log::{debug, error, info, warn};
+use nannou::prelude::ToPrimitive;
+use strum::IntoEnumIterator;
+
+use crate::settings::EaseStyle;
+use crate::tether::{
+    LightMessages, LightResetMessage, LightTriggerMessage, ScheduleOptions, TriggerOptions,
+    Validate,
+};
+
+/// Large enough for any single UDP datagram
+const MAX_PACKET_SIZE: usize = 65536;
+
+/// Seconds between the NTP epoch (1900), used by OSC time tags, and the Unix epoch
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Listens for OSC messages over UDP and maps them to the same
+/// `LightMessages` that Tether produces:
+/// - `/light/trigger <id> <brightness> [attack release final range delay]`
+/// - `/light/reset [brightness] [duration] [style]`
+///
+/// Messages inside a bundle with a time tag are scheduled for that time.
+pub struct OscListener {
+    socket: UdpSocket,
+    buffer: Vec<u8>,
+}
+
+#[derive(Debug)]
+enum OscArg {
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    Bool(bool),
+    Nil,
+}
+
+impl OscArg {
+    fn as_f32(&self) -> Option<f32> {
+        match self {
+            OscArg::Int(v) => v.to_f32(),
+            OscArg::Long(v) => v.to_f32(),
+            OscArg::Float(v) => Some(*v),
+            OscArg::Double(v) => v.to_f32(),
+            OscArg::Bool(v) => Some(if *v { 1. } else { 0. }),
+            OscArg::String(v) => v.parse().ok(),
+            OscArg::Nil => None,
+        }
+    }
+
+    /// Integers only; floats are accepted if they have no fractional part,
+    /// since some controllers send every number as a float (or as a string)
+    fn as_i64(&self) -> Option<i64> {
+        match self {
+            OscArg::Int(v) => Some(i64::from(*v)),
+            OscArg::Long(v) => Some(*v),
+            OscArg::Float(v) if v.fract() == 0. => v.to_i64(),
+            OscArg::Double(v) if v.fract() == 0. => v.to_i64(),
+            OscArg::String(v) => v.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+struct OscMessage {
+    address: String,
+    args: Vec<OscArg>,
+    /// From the time tag of the enclosing bundle, in ms since the Unix epoch
+    execute_at: Option<u64>,
+}
+
+impl OscListener {
+    pub fn new(host: IpAddr, port: u16) -> Result<Self, io::Error> {
+        let socket = UdpSocket::bind(SocketAddr::new(host, port))?;
+        socket.set_nonblocking(true)?;
+        info!("Listening for OSC on {host}:{port}");
+        Ok(OscListener {
+            socket,
+            buffer: vec![0; MAX_PACKET_SIZE],
+        })
+    }
+
+    /// Parse every packet received since the last check
+    pub fn check_messages(&mut self) -> Vec<LightMessages> {
+        let mut light_messages = Vec::new();
+        loop {
+            match self.socket.recv_from(&mut self.buffer) {
+                Ok((size, from)) => {
+                    let mut messages = Vec::new();
+                    match parse_packet(&self.buffer[..size], None, &mut messages) {
+                        Ok(()) => {
+                            for m in messages {
+                                debug!("OSC from {from}: {} {:?}", m.address, m.args);
+                                match to_light_message(&m) {
+                                    Ok(Some(light_message)) => light_messages.push(light_message),
+                                    Ok(None) => debug!("Ignoring OSC address {}", m.address),
+                                    Err(reason) => {
+                                        warn!("Rejected OSC message {}: {reason}", m.address)
+                                    }
+                                }
+                            }
+                        }
+                        Err(reason) => warn!("Failed to parse OSC packet from {from}: {reason}"),
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    error!("Error receiving OSC: {e}");
+                    break;
+                }
+            }
+        }
+        light_messages
+    }
+}
+
+fn to_light_message(m: &OscMessage) -> Result<Option<LightMessages>, String> {
+    let f32_arg = |i: usize, name: &str| -> Result<Option<f32>, String> {
+        m.args
+            .get(i)
+            .map(|a| {
+                a.as_f32()
+                    .ok_or(format!("{name} must be a number, got {a:?}"))
+            })
+            .transpose()
+    };
+    let int_arg = |i: usize, name: &str| -> Result<Option<i64>, String> {
+        m.args
+            .get(i)
+            .map(|a| {
+                a.as_i64()
+                    .ok_or(format!("{name} must be an integer, got {a:?}"))
+            })
+            .transpose()
+    };
+    let usize_arg = |i: usize, name: &str| -> Result<Option<usize>, String> {
+        int_arg(i, name)?
+            .map(|v| {
+                v.to_usize()
+                    .ok_or(format!("{name} must not be negative, got {v}"))
+            })
+            .transpose()
+    };
+    let style_arg = |i: usize, name: &str| -> Result<Option<EaseStyle>, String> {
+        m.args
+            .get(i)
+            .map(|a| match a {
+                OscArg::String(s) => EaseStyle::iter()
+                    .find(|style| style.to_string() == *s)
+                    .ok_or(format!("{name} must be the name of a style, got \"{s}\"")),
+                _ => Err(format!("{name} must be a string, got {a:?}")),
+            })
+            .transpose()
+    };
+    let schedule = ScheduleOptions {
+        execute_at: m.execute_at,
+        execute_in: None,
+    };
+
+    let light_message = match m.address.as_str() {
+        "/light/trigger" => {
+            let parsed = LightTriggerMessage {
+                id: usize_arg(0, "id")?.ok_or("missing id")?,
+                target_brightness: f32_arg(1, "brightness")?.ok_or("missing brightness")?,
+                options: TriggerOptions {
+                    attack_duration: usize_arg(2, "attack")?,
+                    release_duration: usize_arg(3, "release")?,
+                    final_brightness: f32_arg(4, "final")?,
+                    transmission_range: f32_arg(5, "range")?,
+                    transmission_delay: int_arg(6, "delay")?,
+                    ..TriggerOptions::default()
+                },
+                schedule,
+            };
+            parsed.validate()?;
+            LightMessages::Trigger(parsed)
+        }
+        "/light/reset" => {
+            let parsed = LightResetMessage {
+                target_brightness: f32_arg(0, "brightness")?,
+                fade_duration: usize_arg(1, "duration")?,
+                fade_style: style_arg(2, "style")?,
+                schedule,
+            };
+            parsed.validate()?;
+            LightMessages::Reset(parsed)
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(light_message))
+}
+
+fn parse_packet(
+    packet: &[u8],
+    execute_at: Option<u64>,
+    messages: &mut Vec<OscMessage>,
+) -> Result<(), String> {
+    let mut reader = Reader {
+        buf: packet,
+        pos: 0,
+    };
+    if packet.starts_with(b"#bundle\0") {
+        reader.pos = 8;
+        let time_tag = reader.read_u64()?;
+        // Nested bundles without a time tag of their own inherit the outer one
+        let execute_at = time_tag_to_unix_ms(time_tag).or(execute_at);
+        while reader.pos < packet.len() {
+            let size = reader
+                .read_i32()?
+                .to_usize()
+                .ok_or("negative bundle element size")?;
+            let element = reader.read_bytes(size)?;
+            parse_packet(element, execute_at, messages)?;
+        }
+        Ok(())
+    } else {
+        let address = reader.read_string()?;
+        if !address.starts_with('/') {
+            return Err(format!("invalid address pattern \"{address}\""));
+        }
+        let mut args = Vec::new();
+        // Very old implementations may omit the type tag string entirely
+        if reader.pos < packet.len() {
+            let type_tags = reader.read_string()?;
+            let type_tags = type_tags
+                .strip_prefix(',')
+                .ok_or("type tag string must start with ','")?;
+            for tag in type_tags.chars() {
+                args.push(match tag {
+                    'i' => OscArg::Int(reader.read_i32()?),
+                    'h' => OscArg::Long(reader.read_u64()? as i64),
+                    'f' => OscArg::Float(f32::from_bits(reader.read_i32()? as u32)),
+                    'd' => OscArg::Double(f64::from_bits(reader.read_u64()?)),
+                    's' | 'S' => OscArg::String(reader.read_string()?),
+                    'T' => OscArg::Bool(true),
+                    'F' => OscArg::Bool(false),
+                    'N' | 'I' => OscArg::Nil,
+                    other => return Err(format!("unsupported argument type '{other}'")),
+                });
+            }
+        }
+        messages.push(OscMessage {
+            address,
+            args,
+            execute_at,
+        });
+        Ok(())
+    }
+}
+
+/// The special time tag 1 means "immediately"
+fn time_tag_to_unix_ms(time_tag: u64) -> Option<u64> {
+    if time_tag == 1 {
+        return None;
+    }
+    let seconds = (time_tag >> 32).checked_sub(NTP_UNIX_OFFSET)?;
+    let fraction_ms = ((time_tag & 0xFFFF_FFFF) * 1000) >> 32;
+    Some(seconds * 1000 + fraction_ms)
+}
+
+/// Reads big-endian, 4-byte aligned OSC data
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.pos + count;
+        let bytes = self.buf.get(self.pos..end).ok_or("packet is truncated")?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn read_i32(&mut self) -> Result<i32, String> {
+        let bytes = self.read_bytes(4)?;
+        Ok(i32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, String> {
+        let bytes = self.read_bytes(8)?;
+        Ok(u64::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Null-terminated, then padded with nulls to a multiple of 4 bytes
+    fn read_string(&mut self) -> Result<String, String> {
+        let rest = self.buf.get(self.pos..).unwrap_or_default();
+        let length = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or("string is not terminated")?;
+        let s = std::str::from_utf8(&rest[..length])
+            .map_err(|e| format!("string is not valid UTF-8: {e}"))?
+            .to_owned();
+        self.pos = (self.pos + length + 4) & !3;
+        Ok(s)
+    }
+}
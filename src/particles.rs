@@ -1,14 +1,33 @@
 use log::debug;
 use nannou::prelude::{map_range, Point2, ToPrimitive};
 
-use crate::{animation::EnvelopeStage, settings::DEFAULT_COUNT};
+use crate::{
+    animation::{AnimationLayer, EnvelopeStage, LayerOptions},
+    settings::{PhaseSettings, DEFAULT_COUNT},
+};
+
+/// Most layers a particle can hold; beyond this, the oldest layer with the
+/// lowest priority is dropped, or the new layer if its priority is lower still
+const MAX_LAYERS: usize = 16;
 
 pub struct Particle {
     pub id: usize,
     pub order: usize,
     pub position: Point2,
     brightness: f32,
+    /// Level of the ambient layer on this fixture
+    ambient: f32,
+    /// Level of the running effect on this fixture
+    effect: f32,
+    /// Level from the chime's swing, in physics mode
+    physics: f32,
+    /// Multiplier from the opening hours, applied only to the output
+    master: f32,
+    /// The main envelope, which sets `brightness`
     pub animation: EnvelopeStage,
+    /// Further envelopes playing at the same time, composited over the main
+    /// one in order of priority
+    layers: Vec<AnimationLayer>,
 }
 
 impl Particle {
@@ -18,14 +37,122 @@ impl Particle {
             order,
             position,
             brightness: 0.,
+            ambient: 0.,
+            effect: 0.,
+            physics: 0.,
+            master: 1.,
             animation: EnvelopeStage::Idle(),
+            layers: Vec::new(),
         }
     }
+    /// The brightest of the animated brightness (the main envelope with the
+    /// animation layers composited over it), the ambient layer, the effect
+    /// layer and the chime's swing, so that new animations start smoothly
+    /// from whatever is currently showing
     pub fn brightness(&self) -> f32 {
-        self.brightness.clamp(0., 1.0)
+        self.layers
+            .iter()
+            .fold(self.brightness, |below, layer| layer.blend(below))
+            .max(self.ambient)
+            .max(self.effect)
+            .max(self.physics)
+            .clamp(0., 1.0)
+    }
+    pub fn layers(&self) -> &[AnimationLayer] {
+        &self.layers
+    }
+    /// Stop every animation layer, e.g. when the main envelope is replaced
+    /// by a fade that should be all that shows
+    pub fn clear_layers(&mut self) {
+        self.layers.clear();
+    }
+    /// Start another animation layer, keeping the layers in the order they
+    /// are composited
+    pub fn add_layer(&mut self, layer: AnimationLayer) {
+        if self.layers.len() >= MAX_LAYERS {
+            let lowest = self
+                .layers
+                .iter()
+                .map(|l| l.options.priority)
+                .min()
+                .unwrap_or_default();
+            if layer.options.priority < lowest {
+                debug!("#{} too many layers; drop the new one", self.id);
+                return;
+            }
+            if let Some(i) = self
+                .layers
+                .iter()
+                .position(|l| l.options.priority == lowest)
+            {
+                debug!("#{} too many layers; drop the oldest", self.id);
+                self.layers.remove(i);
+            }
+        }
+        let i = self
+            .layers
+            .partition_point(|l| l.options.priority <= layer.options.priority);
+        self.layers.insert(i, layer);
+    }
+    /// Keep the main envelope, or the newest layer with these options, up for
+    /// another attack (see `EnvelopeStage::extend`). Returns false if there
+    /// is no such envelope still running.
+    pub fn extend(
+        &mut self,
+        layer: Option<LayerOptions>,
+        attack: &PhaseSettings,
+        release: &PhaseSettings,
+    ) -> bool {
+        match layer {
+            None => self.animation.extend(self.brightness, attack, release),
+            Some(options) => self
+                .layers
+                .iter_mut()
+                .rev()
+                .find(|l| l.options == options)
+                .is_some_and(|l| l.envelope.extend(l.level, attack, release)),
+        }
+    }
+    /// Move the main envelope and every layer on by `delta_time` ms,
+    /// removing any layers that have finished
+    pub fn update(&mut self, delta_time: usize, release_settings: &PhaseSettings) {
+        let stage = self.animation.name();
+        if let Some(brightness) =
+            self.animation
+                .advance(delta_time, self.brightness, release_settings)
+        {
+            self.brightness = brightness;
+        }
+        if stage != self.animation.name() {
+            debug!("#{} end {stage} => {}", self.id, self.animation.name());
+        }
+
+        for layer in &mut self.layers {
+            if let Some(level) = layer
+                .envelope
+                .advance(delta_time, layer.level, release_settings)
+            {
+                layer.level = level;
+            }
+        }
+        self.layers
+            .retain(|layer| !matches!(layer.envelope, EnvelopeStage::Idle()));
+    }
+    pub fn set_ambient(&mut self, level: f32) {
+        self.ambient = level;
+    }
+    pub fn set_effect(&mut self, level: f32) {
+        self.effect = level;
+    }
+    pub fn set_physics(&mut self, level: f32) {
+        self.physics = level;
+    }
+    pub fn set_master(&mut self, master: f32) {
+        self.master = master;
     }
-    pub fn set_brightness(&mut self, new_value: f32) {
-        self.brightness = new_value;
+    /// What the fixture actually shows, after the opening hours dimming
+    pub fn output_brightness(&self) -> f32 {
+        self.brightness() * self.master
     }
 }
 
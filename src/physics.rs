@@ -0,0 +1,176 @@
+use log::debug;
+use nannou::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
+
+use crate::particles::Particle;
+
+/// Longest step (s) of the simulation; frames are split into steps no
+/// longer than this, to keep the coupled springs stable
+const MAX_STEP: f32 = 0.002;
+
+/// Upper limits of the settings, within which `MAX_STEP` stays stable:
+/// the fastest mode of the row is below √(stiffness + 4·coupling) rad/s
+pub const MAX_STIFFNESS: f32 = 1000.;
+pub const MAX_COUPLING: f32 = 1000.;
+pub const MAX_DAMPING: f32 = 100.;
+
+#[derive(PartialEq, Clone, Copy, Debug, EnumIter, Display, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BrightnessSource {
+    /// Brightest while the chime is moving fastest, at the bottom of its swing
+    KineticEnergy,
+    /// Brightest at the ends of the swing
+    Displacement,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct PhysicsSettings {
+    /// Instead of playing envelopes, triggers push the chimes, which swing
+    /// as pendulums and light up according to their motion
+    pub enabled: bool,
+    /// Restoring force per radian, (rad/s)²; the square of the natural
+    /// frequency of a chime on its own
+    pub stiffness: f32,
+    /// How quickly a swing dies away, per second
+    pub damping: f32,
+    /// Spring constant between neighbouring chimes (in fixture order),
+    /// which passes the swing along the row
+    pub coupling: f32,
+    /// Speed (rad/s) given to a chime by a trigger at full brightness
+    pub impulse: f32,
+    /// The swing (rad) that counts as full brightness
+    pub max_angle: f32,
+    pub source: BrightnessSource,
+}
+
+impl Default for PhysicsSettings {
+    fn default() -> Self {
+        PhysicsSettings {
+            enabled: false,
+            stiffness: 20.,
+            damping: 0.8,
+            coupling: 4.,
+            // Enough to swing a chime on its own out to max_angle
+            impulse: 2.7,
+            max_angle: 0.6,
+            source: BrightnessSource::KineticEnergy,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Chime {
+    /// rad, from hanging straight down
+    angle: f32,
+    /// rad/s
+    velocity: f32,
+}
+
+/// The chimes as a row of damped pendulums, coupled to their neighbours
+/// by springs
+#[derive(Default)]
+pub struct Physics {
+    /// In fixture order, i.e. along the row
+    chimes: Vec<Chime>,
+    /// Impulses waiting for their delay (ms) to run out: (delay, order, speed)
+    pending: Vec<(i64, usize, f32)>,
+}
+
+impl Physics {
+    /// Push a chime (by the particle's order) according to the brightness
+    /// of a trigger, after `delay` ms
+    pub fn impulse(
+        &mut self,
+        settings: &PhysicsSettings,
+        order: usize,
+        brightness: f32,
+        delay: i64,
+    ) {
+        let speed = settings.impulse * brightness;
+        if delay > 0 {
+            self.pending.push((delay, order, speed));
+        } else {
+            self.push(order, speed);
+        }
+    }
+
+    fn push(&mut self, order: usize, speed: f32) {
+        if let Some(chime) = self.chimes.get_mut(order) {
+            chime.velocity += speed;
+            debug!("Chime {order} impulse to {:.2} rad/s", chime.velocity);
+        }
+    }
+
+    /// Angle (rad) of the chime at this order, to draw it swinging
+    pub fn angle(&self, order: usize) -> f32 {
+        self.chimes.get(order).map_or(0., |chime| chime.angle)
+    }
+
+    pub fn update(
+        &mut self,
+        settings: &PhysicsSettings,
+        delta_time: usize,
+        particles: &mut [Particle],
+    ) {
+        if !settings.enabled {
+            self.chimes.clear();
+            self.pending.clear();
+            for p in particles {
+                p.set_physics(0.);
+            }
+            return;
+        }
+        self.chimes.resize(particles.len(), Chime::default());
+
+        let delta = delta_time.to_i64().unwrap();
+        let mut due = Vec::new();
+        self.pending.retain_mut(|(delay, order, speed)| {
+            *delay -= delta;
+            if *delay <= 0 {
+                due.push((*order, *speed));
+            }
+            *delay > 0
+        });
+        for (order, speed) in due {
+            self.push(order, speed);
+        }
+
+        let seconds = delta_time.to_f32().unwrap() / 1000.;
+        let steps = (seconds / MAX_STEP).ceil().max(1.);
+        let dt = seconds / steps;
+        for _ in 0..steps.to_usize().unwrap() {
+            self.step(settings, dt);
+        }
+
+        let max_angle = settings.max_angle.max(0.01);
+        // Speed at the bottom of a swing out to max_angle
+        let max_velocity = max_angle * settings.stiffness.max(0.01).sqrt();
+        for p in particles {
+            let chime = self.chimes[p.order];
+            let level = match settings.source {
+                BrightnessSource::KineticEnergy => (chime.velocity / max_velocity).powi(2),
+                BrightnessSource::Displacement => chime.angle.abs() / max_angle,
+            };
+            p.set_physics(level.clamp(0., 1.));
+        }
+    }
+
+    /// Semi-implicit Euler: new velocities first, then positions from them
+    fn step(&mut self, settings: &PhysicsSettings, dt: f32) {
+        let angles: Vec<f32> = self.chimes.iter().map(|c| c.angle).collect();
+        for (i, chime) in self.chimes.iter_mut().enumerate() {
+            let mut acceleration =
+                -settings.stiffness * chime.angle.sin() - settings.damping * chime.velocity;
+            if i > 0 {
+                acceleration += settings.coupling * (angles[i - 1] - chime.angle);
+            }
+            if let Some(next) = angles.get(i + 1) {
+                acceleration += settings.coupling * (next - chime.angle);
+            }
+            chime.velocity += acceleration * dt;
+            chime.angle += chime.velocity * dt;
+        }
+    }
+}
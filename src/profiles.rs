@@ -0,0 +1,273 @@
+use std::{collections::BTreeMap, fs, io::ErrorKind};
+
+use log::{info, warn};
+use nannou::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::settings::Settings;
+
+pub const DEFAULT_PROFILES_PATH: &str = "./profiles.json";
+
+/// Transition time (ms) when switching profiles, unless the message gives one
+const DEFAULT_TRANSITION: u64 = 3000;
+
+/// Numeric settings that only make sense as whole steps, so they change
+/// straight away instead of being interpolated
+const STEPPED_FIELDS: &[&str] = &["chimesCount", "channelsPerPixel"];
+
+/// Each profile is a settings patch: a profile saved from the app has every
+/// field, but one written by hand only needs the fields it changes
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct ProfileFile {
+    profiles: BTreeMap<String, Value>,
+}
+
+/// The names and state of the profiles, as published on the profiles plug
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileList {
+    pub names: Vec<String>,
+    pub active: Option<String>,
+}
+
+struct Transition {
+    from: Value,
+    /// Only the settings that differ between the two ends, so that other
+    /// settings can still be changed during the transition
+    to: Value,
+    duration: u64,
+    elapsed: u64,
+}
+
+/// Named settings profiles, stored together in one file, which can be
+/// switched at runtime with the numeric settings interpolated along the way
+pub struct Profiles {
+    path: String,
+    profiles: BTreeMap<String, Value>,
+    active: Option<String>,
+    transition: Option<Transition>,
+    /// Used when switching from the UI, or by messages that don't give one
+    pub default_transition: u64,
+}
+
+impl Profiles {
+    /// Load the profiles file; a file that does not exist yet is just empty
+    pub fn load(path: &str) -> Result<Self, String> {
+        let profiles = match fs::read_to_string(path) {
+            Ok(text) => {
+                let file: ProfileFile = serde_json::from_str(&text)
+                    .map_err(|e| format!("invalid profiles file {path}: {e}"))?;
+                if let Some(name) = file.profiles.iter().find(|(_, p)| !p.is_object()) {
+                    return Err(format!("profile \"{}\" must be an object", name.0));
+                }
+                info!("Loaded {} profiles from {path}", file.profiles.len());
+                file.profiles
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                warn!("No profiles file at {path} yet; it will be created when a profile is saved");
+                BTreeMap::new()
+            }
+            Err(e) => return Err(format!("failed to read {path}: {e}")),
+        };
+        Ok(Profiles {
+            path: String::from(path),
+            profiles,
+            active: None,
+            transition: None,
+            default_transition: DEFAULT_TRANSITION,
+        })
+    }
+
+    /// No profiles, saving to the given path
+    pub fn empty(path: &str) -> Self {
+        Profiles {
+            path: String::from(path),
+            profiles: BTreeMap::new(),
+            active: None,
+            transition: None,
+            default_transition: DEFAULT_TRANSITION,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.profiles.keys().cloned().collect()
+    }
+
+    /// The profile last switched to
+    pub fn active(&self) -> Option<&str> {
+        self.active.as_deref()
+    }
+
+    pub fn list(&self) -> ProfileList {
+        ProfileList {
+            names: self.names(),
+            active: self.active.clone(),
+        }
+    }
+
+    /// How far through the transition to the active profile, 0-1, if switching
+    pub fn transition_progress(&self) -> Option<f32> {
+        self.transition
+            .as_ref()
+            .map(|t| (t.elapsed.to_f32().unwrap() / t.duration.max(1).to_f32().unwrap()).min(1.))
+    }
+
+    /// Store the current settings as a profile, replacing any with the same name
+    pub fn save(&mut self, name: &str, settings: &Settings) -> Result<(), String> {
+        if name.trim().is_empty() {
+            return Err(String::from("a profile needs a name"));
+        }
+        let value = serde_json::to_value(settings).expect("Failed to serialise Settings");
+        self.profiles.insert(String::from(name), value);
+        self.active = Some(String::from(name));
+        info!("Saved profile \"{name}\"");
+        self.write()
+    }
+
+    pub fn delete(&mut self, name: &str) -> Result<(), String> {
+        if self.profiles.remove(name).is_none() {
+            return Err(format!("no profile named \"{name}\""));
+        }
+        if self.active.as_deref() == Some(name) {
+            self.active = None;
+        }
+        info!("Deleted profile \"{name}\"");
+        self.write()
+    }
+
+    /// Switch to a profile, interpolating numeric settings over `duration` ms;
+    /// everything else changes straight away. The profile is checked against
+    /// the current settings first, so nothing changes if it is invalid.
+    pub fn switch(
+        &mut self,
+        name: &str,
+        settings: &mut Settings,
+        duration: u64,
+    ) -> Result<(), String> {
+        let patch = self
+            .profiles
+            .get(name)
+            .ok_or_else(|| format!("no profile named \"{name}\""))?;
+
+        let from = serde_json::to_value(&*settings).expect("Failed to serialise Settings");
+        let mut target: Settings =
+            serde_json::from_value(from.clone()).expect("Failed to copy Settings");
+        target
+            .apply_patch(patch)
+            .map_err(|reason| format!("profile \"{name}\": {reason}"))?;
+        let to = changes(
+            &from,
+            &serde_json::to_value(&target).expect("Failed to serialise Settings"),
+        )
+        .unwrap_or_else(|| Value::Object(Default::default()));
+
+        info!("Switch to profile \"{name}\" over {duration}ms");
+        self.active = Some(String::from(name));
+        if duration == 0 {
+            self.transition = None;
+            *settings = target;
+        } else {
+            if let Some(patch) = blend(&from, &to, 0., "", false) {
+                settings.apply_patch(&patch)?;
+            }
+            self.transition = Some(Transition {
+                from,
+                to,
+                duration,
+                elapsed: 0,
+            });
+        }
+        Ok(())
+    }
+
+    /// Move any transition on by `delta_time` ms; returns true when it has
+    /// just finished
+    pub fn update(&mut self, delta_time: usize, settings: &mut Settings) -> bool {
+        let transition = match &mut self.transition {
+            Some(transition) => transition,
+            None => return false,
+        };
+        transition.elapsed += delta_time.to_u64().unwrap();
+        let progress =
+            (transition.elapsed.to_f64().unwrap() / transition.duration.to_f64().unwrap()).min(1.);
+        let finished = progress >= 1.;
+
+        // Everything else changed when the switch started
+        let numbers = blend(&transition.from, &transition.to, progress, "", true);
+        if let Err(reason) = numbers.map_or(Ok(()), |patch| settings.apply_patch(&patch)) {
+            // Should not happen, since both ends are valid; finish straight away
+            warn!("Profile transition failed: {reason}");
+            settings.apply_patch(&transition.to).ok();
+            self.transition = None;
+            return true;
+        }
+        if finished {
+            self.transition = None;
+        }
+        finished
+    }
+
+    fn write(&self) -> Result<(), String> {
+        let file = ProfileFile {
+            profiles: self.profiles.clone(),
+        };
+        let text = serde_json::to_string_pretty(&file).unwrap();
+        fs::write(&self.path, text).map_err(|e| format!("failed to write {}: {e}", self.path))
+    }
+}
+
+/// The parts of `to` that differ from `from`, or None if they are the same
+fn changes(from: &Value, to: &Value) -> Option<Value> {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            let changed: serde_json::Map<String, Value> = to
+                .iter()
+                .filter_map(|(k, v)| {
+                    let value = match from.get(k) {
+                        Some(f) => changes(f, v)?,
+                        None => v.clone(),
+                    };
+                    Some((k.clone(), value))
+                })
+                .collect();
+            (!changed.is_empty()).then_some(Value::Object(changed))
+        }
+        _ => (from != to).then(|| to.clone()),
+    }
+}
+
+/// Settings part of the way (`progress`, 0-1) from one to the other:
+/// numbers in objects are interpolated, anything else is taken from `to`,
+/// or left out if only the numbers are wanted
+fn blend(from: &Value, to: &Value, progress: f64, key: &str, numbers_only: bool) -> Option<Value> {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => Some(Value::Object(
+            to.iter()
+                .filter_map(|(k, v)| {
+                    let value = match from.get(k) {
+                        Some(f) => blend(f, v, progress, k, numbers_only)?,
+                        None if numbers_only => return None,
+                        None => v.clone(),
+                    };
+                    Some((k.clone(), value))
+                })
+                .collect(),
+        )),
+        (Value::Number(a), Value::Number(b)) if !STEPPED_FIELDS.contains(&key) => {
+            let (a_value, b_value) = (a.as_f64().unwrap(), b.as_f64().unwrap());
+            let value = a_value + (b_value - a_value) * progress;
+            if b.is_f64() {
+                Some(serde_json::json!(value))
+            } else {
+                Some(serde_json::json!(value.round().to_i64()))
+            }
+        }
+        _ if numbers_only => None,
+        _ => Some(to.clone()),
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use log::{error, info, warn};
+use nannou::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+use crate::tether::{decode_light_message, LightMessages, PayloadEncoding};
+
+pub const DEFAULT_RECORDING_PATH: &str = "./recording.jsonl";
+
+/// Playback speeds must be finite and above 0
+pub fn check_speed(speed: f32) -> Result<f32, String> {
+    if speed.is_finite() && speed > 0. {
+        Ok(speed)
+    } else {
+        Err(format!("playback speed must be above 0, got {speed}"))
+    }
+}
+
+/// Parse a playback speed from the command line
+pub fn parse_speed(s: &str) -> Result<f32, String> {
+    check_speed(s.parse().map_err(|e| format!("{e}"))?)
+}
+
+/// Whether two paths name the same file, however they are written, e.g.
+/// `show.jsonl` and `./show.jsonl`. A file that doesn't exist yet is
+/// compared by the directory it would be created in.
+pub fn same_file(a: &str, b: &str) -> bool {
+    canonical_path(a) == canonical_path(b)
+}
+
+fn canonical_path(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    if let Ok(canonical) = fs::canonicalize(path) {
+        return canonical;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            fs::canonicalize(parent)
+                .map(|dir| dir.join(name))
+                .unwrap_or_else(|_| path.to_path_buf())
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+/// One line of a recording file (JSON Lines)
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RecordedMessage {
+    /// ms since the recording started
+    time: u64,
+    plug: String,
+    payload: serde_json::Value,
+}
+
+/// Writes every light message that is applied, from any input, to a file as it happens
+pub struct Recorder {
+    path: String,
+    file: BufWriter<File>,
+    started: Instant,
+    count: usize,
+}
+
+impl Recorder {
+    pub fn start(path: &str) -> Result<Self, io::Error> {
+        let file = BufWriter::new(File::create(path)?);
+        info!("Recording light messages to {path}");
+        Ok(Recorder {
+            path: String::from(path),
+            file,
+            started: Instant::now(),
+            count: 0,
+        })
+    }
+
+    pub fn record(&mut self, plug_name: &str, payload: serde_json::Value) {
+        let line = RecordedMessage {
+            time: self.started.elapsed().as_millis().to_u64().unwrap(),
+            plug: String::from(plug_name),
+            payload,
+        };
+        let written = serde_json::to_writer(&mut self.file, &line)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(self.file))
+            // Flush every line, so that nothing is lost if the app is killed
+            .and_then(|()| self.file.flush());
+        match written {
+            Ok(()) => self.count += 1,
+            Err(e) => error!("Failed to write to recording {}: {e}", self.path),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+/// Plays back a recording through the normal message path, at any speed
+pub struct Player {
+    path: String,
+    messages: Vec<RecordedMessage>,
+    /// Playback position in ms (of recorded time)
+    position: f64,
+    /// Index of the next message to play
+    next: usize,
+    speed: f32,
+    pub looping: bool,
+    pub playing: bool,
+}
+
+impl Player {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("failed to open {path}: {e}"))?;
+        let mut messages = Vec::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| format!("failed to read {path}: {e}"))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let message: RecordedMessage =
+                serde_json::from_str(&line).map_err(|e| format!("{path} line {}: {e}", i + 1))?;
+            messages.push(message);
+        }
+        messages.sort_by_key(|m| m.time);
+        info!("Loaded {} recorded messages from {path}", messages.len());
+        Ok(Player {
+            path: String::from(path),
+            messages,
+            position: 0.,
+            next: 0,
+            speed: 1.,
+            looping: false,
+            playing: false,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f32) -> Result<(), String> {
+        self.speed = check_speed(speed)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Time of the last message, in ms
+    pub fn duration(&self) -> u64 {
+        self.messages.last().map_or(0, |m| m.time)
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position.to_u64().unwrap_or(0)
+    }
+
+    /// Jump to a time (ms) in the recording, without playing the
+    /// messages in between
+    pub fn seek(&mut self, position: u64) {
+        let position = position.min(self.duration());
+        self.position = position.to_f64().unwrap();
+        self.next = self.messages.partition_point(|m| m.time < position);
+    }
+
+    /// Move playback on by `delta_time` ms (of real time), returning the
+    /// messages that are now due
+    pub fn advance(&mut self, delta_time: usize) -> Vec<LightMessages> {
+        let mut due = Vec::new();
+        if !self.playing {
+            return due;
+        }
+        self.position += delta_time.to_f64().unwrap() * f64::from(self.speed);
+
+        loop {
+            while let Some(m) = self.messages.get(self.next) {
+                if m.time.to_f64().unwrap() > self.position {
+                    return due;
+                }
+                self.next += 1;
+                let payload = serde_json::to_vec(&m.payload).unwrap();
+                match decode_light_message(&m.plug, &payload, PayloadEncoding::Json) {
+                    Ok(Some(light_message)) => due.push(light_message),
+                    Ok(None) => warn!("Skipping recorded message for unknown plug {}", m.plug),
+                    Err(reason) => warn!("Skipping recorded message: {reason}"),
+                }
+            }
+
+            // Reached the end
+            let duration = self.duration().to_f64().unwrap();
+            if self.looping && duration > 0. {
+                self.position -= duration;
+                self.next = 0;
+            } else {
+                info!("Finished playing {}", self.path);
+                self.playing = false;
+                self.seek(0);
+                return due;
+            }
+        }
+    }
+}
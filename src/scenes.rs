@@ -0,0 +1,142 @@
+use std::{collections::BTreeMap, fs, io::ErrorKind};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    animation::{Animation, EnvelopeStage},
+    particles::Particle,
+    settings::{check_unit_range, get_new_tween, EaseStyle},
+};
+
+pub const DEFAULT_SCENES_PATH: &str = "./scenes.json";
+
+/// A snapshot of every fixture's brightness, by fixture #ID
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Scene {
+    pub name: String,
+    pub brightness: BTreeMap<usize, f32>,
+}
+
+impl Scene {
+    pub fn capture(name: &str, particles: &[Particle]) -> Self {
+        Scene {
+            name: String::from(name),
+            brightness: particles.iter().map(|p| (p.id, p.brightness())).collect(),
+        }
+    }
+
+    /// Crossfade every fixture in the scene from whatever it shows now to its
+    /// level in the scene; fixtures the scene doesn't include are left alone
+    pub fn recall(&self, particles: &mut [Particle], duration: usize, style: EaseStyle) {
+        for p in particles {
+            if let Some(&target_brightness) = self.brightness.get(&p.id) {
+                let fade = Animation::new(
+                    duration,
+                    p.brightness(),
+                    target_brightness,
+                    get_new_tween(&style),
+                );
+                p.animation = EnvelopeStage::ReleaseAnimation(fade);
+                p.clear_layers();
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct SceneFile {
+    scenes: Vec<Scene>,
+}
+
+/// The scenes file, kept in memory and written back whenever a scene is
+/// captured or deleted
+pub struct Scenes {
+    path: String,
+    scenes: Vec<Scene>,
+}
+
+impl Scenes {
+    /// Load the scenes file; a file that does not exist yet is just empty
+    pub fn load(path: &str) -> Result<Self, String> {
+        let scenes = match fs::read_to_string(path) {
+            Ok(text) => {
+                let file: SceneFile = serde_json::from_str(&text)
+                    .map_err(|e| format!("invalid scenes file {path}: {e}"))?;
+                for scene in &file.scenes {
+                    for (id, brightness) in &scene.brightness {
+                        check_unit_range(&format!("brightness of #{id}"), *brightness)
+                            .map_err(|reason| format!("scene \"{}\": {reason}", scene.name))?;
+                    }
+                }
+                info!("Loaded {} scenes from {path}", file.scenes.len());
+                file.scenes
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                warn!("No scenes file at {path} yet; it will be created when a scene is captured");
+                Vec::new()
+            }
+            Err(e) => return Err(format!("failed to read {path}: {e}")),
+        };
+        Ok(Scenes {
+            path: String::from(path),
+            scenes,
+        })
+    }
+
+    /// No scenes, saving to the given path
+    pub fn empty(path: &str) -> Self {
+        Scenes {
+            path: String::from(path),
+            scenes: Vec::new(),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn scenes(&self) -> &[Scene] {
+        &self.scenes
+    }
+
+    pub fn find(&self, name: &str) -> Result<&Scene, String> {
+        self.scenes
+            .iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| format!("no scene named \"{name}\""))
+    }
+
+    /// Store the current state of the fixtures, replacing any scene with the same name
+    pub fn capture(&mut self, name: &str, particles: &[Particle]) -> Result<(), String> {
+        if name.trim().is_empty() {
+            return Err(String::from("a scene needs a name"));
+        }
+        let scene = Scene::capture(name, particles);
+        match self.scenes.iter_mut().find(|s| s.name == name) {
+            Some(existing) => *existing = scene,
+            None => self.scenes.push(scene),
+        }
+        info!("Captured scene \"{name}\"");
+        self.save()
+    }
+
+    pub fn delete(&mut self, name: &str) -> Result<(), String> {
+        let index = self
+            .scenes
+            .iter()
+            .position(|s| s.name == name)
+            .ok_or_else(|| format!("no scene named \"{name}\""))?;
+        self.scenes.remove(index);
+        info!("Deleted scene \"{name}\"");
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let file = SceneFile {
+            scenes: self.scenes.clone(),
+        };
+        let text = serde_json::to_string_pretty(&file).unwrap();
+        fs::write(&self.path, text).map_err(|e| format!("failed to write {}: {e}", self.path))
+    }
+}
@@ -0,0 +1,124 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
+
+use crate::tether::LightMessages;
+
+/// What to do with a message whose execution time had already passed
+/// (by more than the tolerance) when it arrived
+#[derive(PartialEq, Clone, Copy, Debug, EnumIter, Display, Serialize, Deserialize)]
+pub enum ExpiredPolicy {
+    /// Run it straight away, even though it is late
+    Fire,
+    /// Ignore it
+    Drop,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct SchedulingSettings {
+    /// Messages this late (ms) or less are run as if they were on time
+    pub late_tolerance: u64,
+    pub expired_policy: ExpiredPolicy,
+}
+
+impl Default for SchedulingSettings {
+    fn default() -> Self {
+        SchedulingSettings {
+            late_tolerance: 50,
+            expired_policy: ExpiredPolicy::Fire,
+        }
+    }
+}
+
+/// When a message should run, relative to now
+pub enum Due {
+    Now,
+    Later(SystemTime),
+    /// The execution time passed this many ms ago
+    Late(u64),
+}
+
+/// Convert an absolute timestamp (ms since the Unix epoch) or a relative
+/// delay (ms) into the time a message is due
+pub fn due_time(execute_at: Option<u64>, execute_in: Option<u64>, now: SystemTime) -> Due {
+    let due = match (execute_at, execute_in) {
+        (Some(at), _) => UNIX_EPOCH + Duration::from_millis(at),
+        (None, Some(delay)) => now + Duration::from_millis(delay),
+        (None, None) => return Due::Now,
+    };
+    match now.duration_since(due) {
+        Ok(late_by) if late_by.is_zero() => Due::Now,
+        Ok(late_by) => Due::Late(late_by.as_millis() as u64),
+        Err(_) => Due::Later(due),
+    }
+}
+
+/// Where a message came from, so that problems with it can be reported
+/// the same way however late it runs
+#[derive(PartialEq, Clone, Copy, Debug, Display)]
+pub enum MessageSource {
+    Tether,
+    Osc,
+    Http,
+    Recording,
+    Audio,
+    Cue,
+}
+
+struct ScheduledMessage {
+    due: SystemTime,
+    message: LightMessages,
+    source: MessageSource,
+    /// Index of the installation the message is for
+    installation: usize,
+}
+
+/// Queue of messages waiting for their execution time
+#[derive(Default)]
+pub struct Scheduler {
+    /// Kept sorted by due time; messages due at the same time stay in order of arrival
+    queue: Vec<ScheduledMessage>,
+}
+
+impl Scheduler {
+    pub fn schedule(
+        &mut self,
+        due: SystemTime,
+        message: LightMessages,
+        source: MessageSource,
+        installation: usize,
+    ) {
+        let index = self.queue.partition_point(|m| m.due <= due);
+        debug!("Scheduled {source} message #{index} in queue, due at {due:?}");
+        self.queue.insert(
+            index,
+            ScheduledMessage {
+                due,
+                message,
+                source,
+                installation,
+            },
+        );
+    }
+
+    /// Remove and return every message that is due, in order, with where it
+    /// came from and the installation it is for
+    pub fn take_due(&mut self, now: SystemTime) -> Vec<(LightMessages, MessageSource, usize)> {
+        let count = self.queue.partition_point(|m| m.due <= now);
+        self.queue
+            .drain(..count)
+            .map(|m| (m.message, m.source, m.installation))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
@@ -0,0 +1,255 @@
+use std::{fs, path::Path};
+
+use log::{info, warn};
+use nannou::prelude::ToPrimitive;
+use serde::Deserialize;
+
+use crate::tether::{decode_light_message, CueRef, LightMessages, PayloadEncoding};
+
+pub const DEFAULT_CUES_PATH: &str = "./cues.json";
+
+/// A show file: the list of cues, in the order GO plays them
+#[derive(Deserialize, Debug)]
+struct CueList {
+    cues: Vec<Cue>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Cue {
+    pub name: String,
+    #[serde(default)]
+    steps: Vec<CueStep>,
+    /// Automatically GO to the next cue this many ms after this one started
+    pub follow: Option<u64>,
+    /// The cue to put on standby after this one, instead of the
+    /// following one; e.g. the name of an earlier cue to loop
+    pub next: Option<String>,
+}
+
+/// A message to send at a time (ms) after the start of the cue,
+/// exactly as it would arrive on the plug
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CueStep {
+    #[serde(default)]
+    at: u64,
+    plug: String,
+    #[serde(default)]
+    payload: serde_json::Value,
+}
+
+impl CueStep {
+    fn decode(&self) -> Result<LightMessages, String> {
+        let payload = serde_json::to_vec(&self.payload).unwrap();
+        match decode_light_message(&self.plug, &payload, PayloadEncoding::Json)? {
+            Some(LightMessages::Cue(_)) => Err(String::from("cues cannot control the sequencer")),
+            Some(light_message) => Ok(light_message),
+            None => Err(format!("unknown plug \"{}\"", self.plug)),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum SequencerState {
+    Stopped,
+    Playing,
+    Paused,
+}
+
+/// Plays cues from a show file: GO fires the cue on standby, sending its
+/// steps at their times, and puts the next cue on standby. Only one cue
+/// runs at a time; GO abandons any steps of the running cue not yet sent.
+pub struct Sequencer {
+    path: String,
+    cues: Vec<Cue>,
+    state: SequencerState,
+    running: Option<usize>,
+    standby: Option<usize>,
+    /// Time (ms) since the running cue started
+    elapsed: f64,
+    /// Index of the next step of the running cue to send
+    next_step: usize,
+}
+
+impl Sequencer {
+    /// Load a show file, in JSON or (if the extension is `.toml`) TOML
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("failed to read {path}: {e}"))?;
+        let is_toml = Path::new(path)
+            .extension()
+            .is_some_and(|extension| extension == "toml");
+        let mut cue_list: CueList = if is_toml {
+            toml::from_str(&text).map_err(|e| format!("invalid cue list {path}: {e}"))?
+        } else {
+            serde_json::from_str(&text).map_err(|e| format!("invalid cue list {path}: {e}"))?
+        };
+
+        let names: Vec<&str> = cue_list.cues.iter().map(|c| c.name.as_str()).collect();
+        for (i, cue) in cue_list.cues.iter().enumerate() {
+            if names[..i].contains(&cue.name.as_str()) {
+                return Err(format!("duplicate cue \"{}\"", cue.name));
+            }
+            if let Some(next) = &cue.next {
+                if !names.contains(&next.as_str()) {
+                    return Err(format!(
+                        "cue \"{}\" has unknown next cue \"{next}\"",
+                        cue.name
+                    ));
+                }
+            }
+            // Catch mistakes now, rather than in the middle of the show
+            for step in &cue.steps {
+                step.decode()
+                    .map_err(|reason| format!("cue \"{}\": {reason}", cue.name))?;
+            }
+        }
+        for cue in &mut cue_list.cues {
+            cue.steps.sort_by_key(|step| step.at);
+        }
+
+        info!("Loaded {} cues from {path}", cue_list.cues.len());
+        let standby = if cue_list.cues.is_empty() {
+            None
+        } else {
+            Some(0)
+        };
+        Ok(Sequencer {
+            path: String::from(path),
+            cues: cue_list.cues,
+            state: SequencerState::Stopped,
+            running: None,
+            standby,
+            elapsed: 0.,
+            next_step: 0,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn cues(&self) -> &[Cue] {
+        &self.cues
+    }
+
+    pub fn state(&self) -> SequencerState {
+        self.state
+    }
+
+    pub fn running(&self) -> Option<usize> {
+        self.running
+    }
+
+    pub fn standby(&self) -> Option<usize> {
+        self.standby
+    }
+
+    /// Time (ms) since the running cue started
+    pub fn elapsed(&self) -> u64 {
+        self.elapsed.to_u64().unwrap_or(0)
+    }
+
+    pub fn find(&self, cue: &CueRef) -> Result<usize, String> {
+        match cue {
+            CueRef::Index(i) if *i < self.cues.len() => Ok(*i),
+            CueRef::Index(i) => Err(format!("no cue number {i}")),
+            CueRef::Name(name) => self
+                .cues
+                .iter()
+                .position(|c| &c.name == name)
+                .ok_or_else(|| format!("no cue named \"{name}\"")),
+        }
+    }
+
+    /// Fire the cue on standby
+    pub fn go(&mut self) -> Result<(), String> {
+        match self.standby {
+            Some(cue) => {
+                self.start(cue, 0.);
+                Ok(())
+            }
+            None => Err(String::from("no cue on standby; end of the cue list")),
+        }
+    }
+
+    /// Fire a particular cue, whatever is on standby
+    pub fn go_to(&mut self, cue: usize) {
+        self.start(cue, 0.);
+    }
+
+    /// Put a cue on standby, to be fired by the next GO
+    pub fn jump(&mut self, cue: usize) {
+        self.standby = Some(cue);
+    }
+
+    /// Stop the running cue and go back to the top of the cue list
+    pub fn stop(&mut self) {
+        self.state = SequencerState::Stopped;
+        self.running = None;
+        self.standby = if self.cues.is_empty() { None } else { Some(0) };
+    }
+
+    pub fn pause(&mut self) {
+        if self.state == SequencerState::Playing {
+            self.state = SequencerState::Paused;
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if self.state == SequencerState::Paused {
+            self.state = SequencerState::Playing;
+        }
+    }
+
+    fn start(&mut self, cue: usize, elapsed: f64) {
+        info!("GO cue \"{}\"", self.cues[cue].name);
+        self.state = SequencerState::Playing;
+        self.running = Some(cue);
+        self.elapsed = elapsed;
+        self.next_step = 0;
+        self.standby = match &self.cues[cue].next {
+            Some(next) => self.cues.iter().position(|c| &c.name == next),
+            None if cue + 1 < self.cues.len() => Some(cue + 1),
+            None => None,
+        };
+    }
+
+    /// Move the running cue on by `delta_time` ms, returning the
+    /// messages from its steps that are now due
+    pub fn advance(&mut self, delta_time: usize) -> Vec<LightMessages> {
+        let mut due = Vec::new();
+        if self.state != SequencerState::Playing {
+            return due;
+        }
+        self.elapsed += delta_time.to_f64().unwrap();
+
+        while let Some(running) = self.running {
+            let cue = &self.cues[running];
+            while let Some(step) = cue.steps.get(self.next_step) {
+                if step.at.to_f64().unwrap() > self.elapsed {
+                    break;
+                }
+                self.next_step += 1;
+                match step.decode() {
+                    Ok(light_message) => due.push(light_message),
+                    Err(reason) => warn!("Skipping step of cue \"{}\": {reason}", cue.name),
+                }
+            }
+
+            match (cue.follow, self.standby) {
+                (Some(follow), Some(standby)) if self.elapsed >= follow.to_f64().unwrap() => {
+                    // Carry over the time since the follow was due, so that
+                    // loops keep time; a follow of 0 still waits a frame
+                    let carried = (self.elapsed - follow.to_f64().unwrap()).max(0.);
+                    self.start(standby, carried);
+                    if follow == 0 {
+                        break;
+                    }
+                }
+                _ => break,
+            }
+        }
+        due
+    }
+}
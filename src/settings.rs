@@ -1,16 +1,38 @@
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory, Parser};
 use log::{debug, error, info, warn};
 use nannou::prelude::*;
+use nannou::rand::rand::{rngs::StdRng, SeedableRng};
 use nannou_egui::Egui;
 use serde::{Deserialize, Serialize};
 use tween::*;
 
+use crate::ambient::{Ambient, AmbientSettings};
+use crate::animation::LayerOptions;
 use crate::artnet::{ArtNetInterface, ArtNetMode};
+use crate::audio::{AudioPlayer, AudioSettings, PendingAudio};
+use crate::debounce::{DebounceSettings, Debouncer};
+use crate::effects::{EffectSettings, Effects, STROBE_DURATION_LIMIT, STROBE_RATE_LIMIT};
+use crate::http_api::HttpApi;
+use crate::installations::Installations;
+use crate::jitter::JitterSettings;
+use crate::merge::MergeSettings;
+use crate::opening_hours::{OpeningHours, OpeningHoursSettings};
+use crate::osc::OscListener;
 use crate::particles::build_layout;
 use crate::particles::Particle;
-use crate::tether::TetherAgent;
+use crate::physics::{Physics, PhysicsSettings, MAX_COUPLING, MAX_DAMPING, MAX_STIFFNESS};
+use crate::profiles::{Profiles, DEFAULT_PROFILES_PATH};
+use crate::recording::{parse_speed, same_file, Player, Recorder, DEFAULT_RECORDING_PATH};
+use crate::scenes::{Scenes, DEFAULT_SCENES_PATH};
+use crate::scheduler::{Scheduler, SchedulingSettings};
+use crate::sequencer::{Sequencer, DEFAULT_CUES_PATH};
+use crate::tempo::{beats_to_ms, Tempo, TempoSettings, MAX_BPM, MIN_BPM};
+use crate::tether::{
+    check_duration, parse_plug_encoding, InputFilter, PayloadEncoding, PlugEncodings, TetherAgent,
+};
+use crate::ActivationTarget;
 
 use strum_macros::Display;
 use strum_macros::EnumIter;
@@ -40,7 +62,17 @@ pub const DEFAULT_HEIGHT_RATIO: f32 = 0.2;
 const DEFAULT_TRANSMISSION_RANGE: f32 = 0.15;
 const DEFAULT_TRANSMISSION_DELAY: i64 = 500;
 
+const DEFAULT_PHYSICAL_ROW_WIDTH: f32 = 6.0;
+const DEFAULT_SCENE_FADE_DURATION: usize = 2000;
+const DEFAULT_SCENE_FADE_STYLE: EaseStyle = EaseStyle::SineBoth;
+
 const TETHER_HOST: std::net::IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+const DEFAULT_TETHER_ROLE: &str = "particleLights";
+const DEFAULT_TETHER_ID: &str = "any";
+const OSC_HOST: std::net::IpAddr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
+const DEFAULT_OSC_PORT: u16 = 9000;
+const HTTP_HOST: std::net::IpAddr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
+const DEFAULT_HTTP_PORT: u16 = 8080;
 const UNICAST_SRC: std::net::IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 102));
 const UNICAST_DST: std::net::IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
 
@@ -61,6 +93,104 @@ pub struct Cli {
     #[arg(long = "tether.host", default_value_t=TETHER_HOST)]
     tether_host: std::net::IpAddr,
 
+    /// The Tether agent role used for this app's output plugs, e.g. settings state
+    #[arg(long = "tether.role", default_value_t=String::from(DEFAULT_TETHER_ROLE))]
+    tether_role: String,
+
+    /// The Tether agent id used for this app's output plugs
+    #[arg(long = "tether.id", default_value_t=String::from(DEFAULT_TETHER_ID))]
+    tether_id: String,
+
+    /// Only accept input messages from agents with this role ("+" for any)
+    #[arg(long = "tether.inputRole", default_value_t=String::from("+"))]
+    tether_input_role: String,
+
+    /// Only accept input messages from agents with one of these ids / group names,
+    /// comma-separated ("+" for any); several ids run an independent installation each
+    #[arg(long = "tether.inputIds", value_delimiter = ',', default_value = "+")]
+    tether_input_ids: Vec<String>,
+
+    /// Payload encoding expected on the input plugs; "auto" accepts both JSON and MessagePack
+    #[arg(long = "tether.encoding", value_enum, default_value_t = PayloadEncoding::Auto)]
+    tether_encoding: PayloadEncoding,
+
+    /// Override the payload encoding for a single plug, e.g. "lightTriggers=json" (repeatable)
+    #[arg(long = "tether.plugEncoding", value_parser = parse_plug_encoding)]
+    tether_plug_encodings: Vec<(String, PayloadEncoding)>,
+
+    /// Flag to enable the OSC listener (independent of Tether)
+    #[arg(long = "osc.enable")]
+    osc_enable: bool,
+
+    /// Interface address to listen for OSC on
+    #[arg(long = "osc.host", default_value_t=OSC_HOST)]
+    osc_host: std::net::IpAddr,
+
+    /// UDP port to listen for OSC on
+    #[arg(long = "osc.port", default_value_t=DEFAULT_OSC_PORT)]
+    osc_port: u16,
+
+    /// Flag to enable the local HTTP + WebSocket control API
+    #[arg(long = "http.enable")]
+    http_enable: bool,
+
+    /// Interface address to serve the HTTP API on
+    #[arg(long = "http.host", default_value_t=HTTP_HOST)]
+    http_host: std::net::IpAddr,
+
+    /// TCP port to serve the HTTP API on
+    #[arg(long = "http.port", default_value_t=DEFAULT_HTTP_PORT)]
+    http_port: u16,
+
+    /// Record every light message that runs to this file (JSON Lines)
+    #[arg(long = "record")]
+    record: Option<String>,
+
+    /// Play back a recording made with --record
+    #[arg(long = "replay")]
+    replay: Option<String>,
+
+    /// Playback speed of the recording, e.g. 2 for double speed
+    #[arg(long = "replay.speed", default_value_t = 1.0, value_parser = parse_speed)]
+    replay_speed: f32,
+
+    /// Start the recording again from the beginning when it ends
+    #[arg(long = "replay.loop")]
+    replay_loop: bool,
+
+    /// Start playback this many ms into the recording
+    #[arg(long = "replay.seek", default_value_t = 0)]
+    replay_seek: u64,
+
+    /// Analyse a WAV file and play it as triggers, e.g. to pre-visualise
+    /// a piece against its soundtrack
+    #[arg(long = "audio")]
+    audio: Option<String>,
+
+    /// Start the audio file again from the beginning when it ends
+    #[arg(long = "audio.loop")]
+    audio_loop: bool,
+
+    /// Load a cue list (show file) for the sequencer, in JSON or TOML
+    #[arg(long = "cues")]
+    cues: Option<String>,
+
+    /// GO the first cue as soon as the cue list is loaded
+    #[arg(long = "cues.go")]
+    cues_go: bool,
+
+    /// Scenes file to load, and to save captured scenes to
+    #[arg(long = "scenes", default_value_t=String::from(DEFAULT_SCENES_PATH))]
+    scenes: String,
+
+    /// Settings profiles file to load, and to save profiles to
+    #[arg(long = "profiles", default_value_t=String::from(DEFAULT_PROFILES_PATH))]
+    profiles: String,
+
+    /// Switch to this profile at startup, after loading the settings file
+    #[arg(long = "profile")]
+    profile: Option<String>,
+
     /// Flag to enable ArtNet broadcast mode (good for development)
     #[arg(long = "artnet.broadcast")]
     artnet_broadcast: bool,
@@ -85,28 +215,77 @@ pub struct Cli {
     #[arg(long = "artnet.pixelChannels", default_value_t = 1)]
     artnet_channels_per_fixture: usize,
 
+    /// Seed for the jitter's random variations, to repeat a run exactly;
+    /// without one, a seed is picked and logged
+    #[arg(long = "seed")]
+    seed: Option<u64>,
+
     /// Start with minimum graphics (no chime graphics, no GUI)
     #[arg(long = "minGraphics")]
     pub use_min_graphics: bool,
 
+    /// Run without a window, e.g. on a machine with no display; the engine
+    /// (Tether, OSC, HTTP, recordings, audio, cues and Art-Net output) runs
+    /// on a timer instead
+    #[arg(long = "headless")]
+    pub headless: bool,
+
     /// Ignore the settings.json file, even if it exists; apply hard-coded defaults instead
     #[arg(long = "ignoreFile")]
     ignore_settings_file: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Cli {
+    /// Exit with a usage error if options that can't work together are given
+    pub fn check(&self) {
+        if let (Some(record), Some(replay)) = (&self.record, &self.replay) {
+            if same_file(record, replay) {
+                Cli::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        format!(
+                            "--record and --replay are the same file ({replay}), \
+                             which recording would overwrite before it is played"
+                        ),
+                    )
+                    .exit();
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub struct PhaseSettings {
     pub duration: usize,
     pub style: EaseStyle,
+    /// If set, the duration is this many beats of the tempo clock instead
+    #[serde(default)]
+    pub beats: Option<f32>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl PhaseSettings {
+    /// The duration in ms, at the given tempo if it is set in beats
+    pub fn duration_at(&self, bpm: f32) -> usize {
+        self.beats
+            .map_or(self.duration, |beats| beats_to_ms(beats, bpm))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 #[serde(rename_all = "camelCase")]
 pub struct TransmissionSettings {
     pub max_range: f32,
     pub max_delay: i64,
 }
 
+/// A named set of fixtures that can be activated together by a batch trigger.
+/// The ids are interpreted the same way as remote trigger ids (by order or #ID).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FixtureGroup {
+    pub name: String,
+    pub ids: Vec<usize>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Settings {
@@ -132,10 +311,275 @@ pub struct Settings {
     #[serde(skip)]
     pub fixture_order_editing: bool,
     pub fixture_order: [usize; DEFAULT_COUNT],
+    #[serde(default)]
+    pub fixture_groups: Vec<FixtureGroup>,
+    /// Real-world length (metres) of the row of fixtures, for position triggers
+    #[serde(default = "default_physical_row_width")]
+    pub physical_row_width: f32,
+    #[serde(default)]
+    pub scheduling: SchedulingSettings,
+    #[serde(default)]
+    pub debounce: DebounceSettings,
+    #[serde(default)]
+    pub merge: MergeSettings,
+    /// If set, transmission plays in layers over the neighbours' own
+    /// animations instead of merging with them
+    #[serde(default)]
+    pub transmission_layer: Option<LayerOptions>,
+    #[serde(default)]
+    pub ambient: AmbientSettings,
+    #[serde(default)]
+    pub effects: EffectSettings,
+    #[serde(default)]
+    pub opening_hours: OpeningHoursSettings,
+    #[serde(default)]
+    pub physics: PhysicsSettings,
+    #[serde(default)]
+    pub audio: AudioSettings,
+    #[serde(default)]
+    pub tempo: TempoSettings,
+    #[serde(default)]
+    pub jitter: JitterSettings,
+    /// Crossfade time (ms) when recalling a scene, unless the message gives one
+    #[serde(default = "default_scene_fade_duration")]
+    pub scene_fade_duration: usize,
+    /// Crossfade curve when recalling a scene, unless the message gives one
+    #[serde(default = "default_scene_fade_style")]
+    pub scene_fade_style: EaseStyle,
     pub artnet_update_interval: u64,
 }
 
 impl Settings {
+    /// Check that every value is usable by the animation and output code
+    pub fn validate(&self) -> Result<(), String> {
+        fn positive(name: &str, value: f32) -> Result<(), String> {
+            if value.is_finite() && value > 0. {
+                Ok(())
+            } else {
+                Err(format!("{name} must be a positive number, got {value}"))
+            }
+        }
+
+        if self.chimes_count == 0 || self.chimes_count > DEFAULT_COUNT {
+            return Err(format!(
+                "chimesCount must be between 1 and {DEFAULT_COUNT}, got {}",
+                self.chimes_count
+            ));
+        }
+        if self.channels_per_pixel == 0 {
+            return Err(String::from("channelsPerPixel must be at least 1"));
+        }
+        if self.artnet_update_interval == 0 {
+            return Err(String::from("artnetUpdateInterval must be at least 1ms"));
+        }
+        positive("chimeThickness", self.chime_thickness)?;
+        positive("chimeLength", self.chime_length)?;
+        positive("physicalRowWidth", self.physical_row_width)?;
+        if self.attack_settings.duration == 0 || self.release_settings.duration == 0 {
+            return Err(String::from(
+                "attack/release durations must be at least 1ms",
+            ));
+        }
+        for (name, beats) in [
+            ("attackSettings.beats", self.attack_settings.beats),
+            ("releaseSettings.beats", self.release_settings.beats),
+        ] {
+            if let Some(beats) = beats {
+                positive(name, beats)?;
+                // As long as it could be, at the slowest tempo
+                check_duration(name, Some(beats_to_ms(beats, MIN_BPM)))?;
+            }
+        }
+        check_unit_range("jitter.attackDuration", self.jitter.attack_duration)?;
+        check_unit_range("jitter.releaseDuration", self.jitter.release_duration)?;
+        check_unit_range("jitter.brightness", self.jitter.brightness)?;
+        if self.jitter.transmission_delay < 0 {
+            return Err(format!(
+                "jitter.transmissionDelay must not be negative, got {}",
+                self.jitter.transmission_delay
+            ));
+        }
+        if !(MIN_BPM..=MAX_BPM).contains(&self.tempo.bpm) {
+            return Err(format!(
+                "tempo.bpm must be between {MIN_BPM} and {MAX_BPM}, got {}",
+                self.tempo.bpm
+            ));
+        }
+        check_unit_range(
+            "transmissionSettings.maxRange",
+            self.transmission_settings.max_range,
+        )?;
+        if self.transmission_settings.max_delay < 0 {
+            return Err(format!(
+                "transmissionSettings.maxDelay must not be negative, got {}",
+                self.transmission_settings.max_delay
+            ));
+        }
+        check_unit_range("mouseBrightnessValue", self.mouse_brightness_value)?;
+        check_unit_range("restingBrightness", self.resting_brightness)?;
+        check_unit_range("ambient.level", self.ambient.level)?;
+        check_unit_range("effects.level", self.effects.level)?;
+        for period in &self.opening_hours.dimming {
+            check_unit_range("openingHours.dimming.brightness", period.brightness)?;
+        }
+        let EffectSettings {
+            speed,
+            width,
+            wavelength,
+            rate,
+            max_strobe_rate,
+            ..
+        } = self.effects;
+        for (name, value) in [
+            ("effects.speed", speed),
+            ("effects.width", width),
+            ("effects.wavelength", wavelength),
+            ("effects.rate", rate),
+            ("effects.maxStrobeRate", max_strobe_rate),
+        ] {
+            if !(value.is_finite() && value >= 0.) {
+                return Err(format!("{name} must not be negative, got {value}"));
+            }
+        }
+        if max_strobe_rate > STROBE_RATE_LIMIT {
+            return Err(format!(
+                "effects.maxStrobeRate must be at most {STROBE_RATE_LIMIT}, got {max_strobe_rate}"
+            ));
+        }
+        if self.effects.max_strobe_duration > STROBE_DURATION_LIMIT {
+            return Err(format!(
+                "effects.maxStrobeDuration must be at most {STROBE_DURATION_LIMIT}, got {}",
+                self.effects.max_strobe_duration
+            ));
+        }
+        if !(self.ambient.twinkle_rate.is_finite() && self.ambient.twinkle_rate >= 0.) {
+            return Err(format!(
+                "ambient.twinkleRate must not be negative, got {}",
+                self.ambient.twinkle_rate
+            ));
+        }
+        let PhysicsSettings {
+            stiffness,
+            damping,
+            coupling,
+            impulse,
+            ..
+        } = self.physics;
+        for (name, value) in [
+            ("physics.stiffness", stiffness),
+            ("physics.damping", damping),
+            ("physics.coupling", coupling),
+            ("physics.impulse", impulse),
+        ] {
+            if !(value.is_finite() && value >= 0.) {
+                return Err(format!("{name} must not be negative, got {value}"));
+            }
+        }
+        for (name, value, max) in [
+            ("physics.stiffness", stiffness, MAX_STIFFNESS),
+            ("physics.damping", damping, MAX_DAMPING),
+            ("physics.coupling", coupling, MAX_COUPLING),
+        ] {
+            if value > max {
+                return Err(format!("{name} must be at most {max}, got {value}"));
+            }
+        }
+        positive("physics.maxAngle", self.physics.max_angle)?;
+        if !(1..=32).contains(&self.audio.bands) {
+            return Err(format!(
+                "audio.bands must be between 1 and 32, got {}",
+                self.audio.bands
+            ));
+        }
+        if !(self.audio.sensitivity.is_finite() && self.audio.sensitivity >= 0.) {
+            return Err(format!(
+                "audio.sensitivity must not be negative, got {}",
+                self.audio.sensitivity
+            ));
+        }
+
+        let mut seen = [false; DEFAULT_COUNT];
+        for id in self.fixture_order {
+            if id >= DEFAULT_COUNT || seen[id] {
+                return Err(format!(
+                    "fixtureOrder must contain each id 0..{} exactly once",
+                    DEFAULT_COUNT - 1
+                ));
+            }
+            seen[id] = true;
+        }
+
+        for (i, group) in self.fixture_groups.iter().enumerate() {
+            if group.name.is_empty() {
+                return Err(String::from("fixtureGroups names must not be empty"));
+            }
+            if self.fixture_groups[..i]
+                .iter()
+                .any(|g| g.name == group.name)
+            {
+                return Err(format!("duplicate fixture group \"{}\"", group.name));
+            }
+            if let Some(id) = group.ids.iter().find(|id| **id >= DEFAULT_COUNT) {
+                return Err(format!(
+                    "fixture group \"{}\" contains unknown id {id}",
+                    group.name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply a partial update, e.g. `{ "releaseSettings": { "duration": 500 } }`.
+    /// Nested objects are merged, anything else is replaced. The update is rejected
+    /// (and the current settings left untouched) if it contains unknown fields
+    /// or results in invalid settings.
+    pub fn apply_patch(&mut self, patch: &serde_json::Value) -> Result<(), String> {
+        fn merge(
+            target: &mut serde_json::Value,
+            patch: &serde_json::Value,
+            path: &str,
+        ) -> Result<(), String> {
+            match (target, patch) {
+                (serde_json::Value::Object(target), serde_json::Value::Object(patch)) => {
+                    for (key, value) in patch {
+                        let field_path = if path.is_empty() {
+                            key.to_owned()
+                        } else {
+                            format!("{path}.{key}")
+                        };
+                        match target.get_mut(key) {
+                            Some(existing) => merge(existing, value, &field_path)?,
+                            None => return Err(format!("unknown settings field \"{field_path}\"")),
+                        }
+                    }
+                    Ok(())
+                }
+                (target, patch) => {
+                    *target = patch.clone();
+                    Ok(())
+                }
+            }
+        }
+
+        if !patch.is_object() {
+            return Err(String::from("settings patch must be an object"));
+        }
+
+        let mut merged = serde_json::to_value(&*self).expect("Failed to serialise Settings");
+        merge(&mut merged, patch, "")?;
+        let updated = serde_json::from_value::<Settings>(merged)
+            .map_err(|e| format!("invalid settings: {e}"))?;
+        updated.validate()?;
+
+        *self = Settings {
+            fixture_order_string: fixture_array_to_string(&updated.fixture_order),
+            fixture_order_editing: false,
+            ..updated
+        };
+        Ok(())
+    }
+
     pub fn save(&self) -> Result<(), ()> {
         let file_path = DEFAULT_SETTINGS_FILE_PATH;
         let text = serde_json::to_string_pretty(self).expect("Failed to serialise Settings");
@@ -155,12 +599,20 @@ impl Settings {
         let file_path = DEFAULT_SETTINGS_FILE_PATH;
         match std::fs::read_to_string(file_path) {
             Ok(text) => match serde_json::from_str::<Settings>(&text) {
-                Ok(data) => {
-                    *self = Settings { ..data };
-                    info!("Loaded settings from file {file_path} ok");
-                    debug!("Loaded: {:?}", self);
-                    Ok(())
-                }
+                // Checked like a patch, so that the current settings are kept
+                // if the file has anything the engine can't run with
+                Ok(data) => match data.validate() {
+                    Ok(()) => {
+                        *self = Settings { ..data };
+                        info!("Loaded settings from file {file_path} ok");
+                        debug!("Loaded: {:?}", self);
+                        Ok(())
+                    }
+                    Err(reason) => {
+                        error!("Invalid settings in {file_path}: {reason}");
+                        Err(())
+                    }
+                },
                 Err(e) => {
                     error!("Failed to parse settings data: {e}");
                     Err(())
@@ -175,25 +627,160 @@ impl Settings {
 }
 
 pub struct Model {
-    pub window_id: WindowId,
+    /// None when running headless
+    pub window_id: Option<WindowId>,
     pub particles: Vec<Particle>,
     pub mouse_position: Point2,
-    pub egui: Egui,
+    /// None when running headless
+    pub egui: Option<Egui>,
     pub settings: Settings,
     pub artnet: ArtNetInterface,
     pub tether: TetherAgent,
+    pub osc: Option<OscListener>,
+    pub http: Option<HttpApi>,
+    pub recorder: Option<Recorder>,
+    pub player: Option<Player>,
+    /// File to record to / play back from the UI
+    pub recording_path: String,
+    pub audio: Option<AudioPlayer>,
+    /// A WAV file still being analysed, which replaces `audio` when it is ready
+    pub audio_pending: Option<PendingAudio>,
+    /// Path of the WAV file to analyse, from the UI
+    pub audio_path: String,
+    pub sequencer: Option<Sequencer>,
+    /// Cue list to load from the UI
+    pub cues_path: String,
+    pub scheduler: Scheduler,
+    /// Holds the triggers being coalesced, until their window ends
+    pub debouncer: Debouncer<ActivationTarget>,
+    pub ambient: Ambient,
+    pub effects: Effects,
+    pub opening_hours: OpeningHours,
+    pub physics: Physics,
+    pub tempo: Tempo,
+    /// Seed the jitter's random generator started from
+    pub seed: u64,
+    /// Only the jitter draws from this, so that a run can be repeated with
+    /// the same seed however the ambient and effect layers behave
+    pub jitter_rng: StdRng,
+    pub scenes: Scenes,
+    /// Name to capture a scene as, from the UI
+    pub scene_name: String,
+    pub profiles: Profiles,
+    /// Name to save the current settings as, from the UI
+    pub profile_name: String,
     pub last_artnet_sent: std::time::SystemTime,
+    pub installations: Installations,
 }
 
 impl Model {
-    pub fn defaults(window_id: WindowId, egui: Egui, cli: &Cli) -> Self {
-        let mut tether = TetherAgent::new(cli.tether_host);
+    pub fn defaults(window_id: Option<WindowId>, egui: Option<Egui>, cli: &Cli) -> Self {
+        let mut tether = TetherAgent::new(
+            cli.tether_host,
+            &cli.tether_role,
+            &cli.tether_id,
+            InputFilter {
+                role: cli.tether_input_role.clone(),
+                ids: cli.tether_input_ids.clone(),
+            },
+            PlugEncodings {
+                default: cli.tether_encoding,
+                overrides: cli.tether_plug_encodings.clone(),
+            },
+        );
         if !cli.tether_disable {
             tether.connect();
         } else {
             warn!("Tether connection disabled")
         }
 
+        let osc = if cli.osc_enable {
+            match OscListener::new(cli.osc_host, cli.osc_port) {
+                Ok(listener) => Some(listener),
+                Err(e) => {
+                    error!("Failed to start OSC listener on port {}: {e}", cli.osc_port);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let http = if cli.http_enable {
+            match HttpApi::start(cli.http_host, cli.http_port) {
+                Ok(api) => Some(api),
+                Err(e) => {
+                    error!("Failed to start HTTP API on port {}: {e}", cli.http_port);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let player = cli
+            .replay
+            .as_ref()
+            .and_then(|path| match Player::load(path) {
+                Ok(mut player) => {
+                    // Already checked by parse_speed
+                    player.set_speed(cli.replay_speed).unwrap();
+                    player.looping = cli.replay_loop;
+                    player.seek(cli.replay_seek);
+                    player.playing = true;
+                    Some(player)
+                }
+                Err(e) => {
+                    error!("Failed to load recording: {e}");
+                    None
+                }
+            });
+
+        // After loading the player, since starting a recording truncates its file
+        let recorder = cli
+            .record
+            .as_ref()
+            .and_then(|path| match Recorder::start(path) {
+                Ok(recorder) => Some(recorder),
+                Err(e) => {
+                    error!("Failed to start recording to {path}: {e}");
+                    None
+                }
+            });
+
+        // Never the file being replayed, which starting a recording would overwrite
+        let recording_path = cli
+            .record
+            .clone()
+            .unwrap_or_else(|| String::from(DEFAULT_RECORDING_PATH));
+
+        let sequencer = cli
+            .cues
+            .as_ref()
+            .and_then(|path| match Sequencer::load(path) {
+                Ok(mut sequencer) => {
+                    if cli.cues_go {
+                        if let Err(e) = sequencer.go() {
+                            warn!("Could not GO: {e}");
+                        }
+                    }
+                    Some(sequencer)
+                }
+                Err(e) => {
+                    error!("Failed to load cue list: {e}");
+                    None
+                }
+            });
+        let cues_path = cli
+            .cues
+            .clone()
+            .unwrap_or_else(|| String::from(DEFAULT_CUES_PATH));
+
+        let scenes = Scenes::load(&cli.scenes).unwrap_or_else(|e| {
+            error!("Failed to load scenes: {e}");
+            Scenes::empty(&cli.scenes)
+        });
+
         let mut artnet = {
             if cli.artnet_broadcast {
                 ArtNetInterface::new(ArtNetMode::Broadcast)
@@ -215,10 +802,12 @@ impl Model {
             attack_settings: PhaseSettings {
                 duration: DEFAULT_ATTACK_DURATION,
                 style: EaseStyle::SineBoth,
+                beats: None,
             },
             release_settings: PhaseSettings {
                 duration: DEFAULT_RELEASE_DURATION,
                 style: EaseStyle::SineBoth,
+                beats: None,
             },
             transmission_settings: TransmissionSettings {
                 max_range: DEFAULT_TRANSMISSION_RANGE,
@@ -235,6 +824,21 @@ impl Model {
             fixture_order: DEFAULT_ORDER,
             fixture_order_string: fixture_array_to_string(&DEFAULT_ORDER),
             fixture_order_editing: false,
+            fixture_groups: Vec::new(),
+            physical_row_width: DEFAULT_PHYSICAL_ROW_WIDTH,
+            scheduling: SchedulingSettings::default(),
+            debounce: DebounceSettings::default(),
+            merge: MergeSettings::default(),
+            transmission_layer: None,
+            ambient: AmbientSettings::default(),
+            effects: EffectSettings::default(),
+            opening_hours: OpeningHoursSettings::default(),
+            physics: PhysicsSettings::default(),
+            audio: AudioSettings::default(),
+            tempo: TempoSettings::default(),
+            jitter: JitterSettings::default(),
+            scene_fade_duration: DEFAULT_SCENE_FADE_DURATION,
+            scene_fade_style: DEFAULT_SCENE_FADE_STYLE,
             use_min_graphics: cli.use_min_graphics,
             artnet_update_interval: (1000. / cli.artnet_update_frequency.to_f32())
                 .to_u64()
@@ -254,12 +858,44 @@ impl Model {
             }
         }
 
+        let mut profiles = Profiles::load(&cli.profiles).unwrap_or_else(|e| {
+            error!("Failed to load profiles: {e}");
+            Profiles::empty(&cli.profiles)
+        });
+        if let Some(name) = &cli.profile {
+            if let Err(e) = profiles.switch(name, &mut settings, 0) {
+                error!("Failed to switch to profile: {e}");
+            }
+        }
+
+        let audio_pending = cli.audio.as_ref().map(|path| {
+            let mut pending = PendingAudio::start(path, settings.audio.bands);
+            pending.looping = cli.audio_loop;
+            pending.playing = true;
+            pending
+        });
+        let audio_path = cli.audio.clone().unwrap_or_default();
+
+        let seed = cli.seed.unwrap_or_else(nannou::rand::random);
+        info!("Random seed {seed}; pass --seed {seed} to repeat this run");
+
+        let layout_width = DEFAULT_WINDOW_W.to_f32() * DEFAULT_WIDTH_RATIO;
+        let layout_height = DEFAULT_WINDOW_H.to_f32() * DEFAULT_HEIGHT_RATIO;
+        let mut installations = Installations::new(&cli.tether_input_ids);
+        installations.lay_out_parked(
+            DEFAULT_COUNT,
+            layout_width,
+            layout_height,
+            &settings.fixture_order,
+        );
+        tether.set_output_groups(installations.group_ids());
+
         Model {
             window_id,
             particles: build_layout(
                 DEFAULT_COUNT,
-                DEFAULT_WINDOW_W.to_f32() * DEFAULT_WIDTH_RATIO,
-                DEFAULT_WINDOW_H.to_f32() * DEFAULT_HEIGHT_RATIO,
+                layout_width,
+                layout_height,
                 &settings.fixture_order,
             ),
             mouse_position: Point2::new(0., 0.),
@@ -267,11 +903,56 @@ impl Model {
             egui,
             artnet,
             tether,
+            osc,
+            http,
+            recorder,
+            player,
+            recording_path,
+            audio: None,
+            audio_pending,
+            audio_path,
+            sequencer,
+            cues_path,
+            scheduler: Scheduler::default(),
+            debouncer: Debouncer::default(),
+            ambient: Ambient::default(),
+            effects: Effects::default(),
+            opening_hours: OpeningHours::default(),
+            physics: Physics::default(),
+            tempo: Tempo::default(),
+            seed,
+            jitter_rng: StdRng::seed_from_u64(seed),
+            scenes,
+            scene_name: String::new(),
+            profiles,
+            profile_name: String::new(),
             last_artnet_sent: std::time::SystemTime::now(),
+            installations,
         }
     }
 }
 
+fn default_physical_row_width() -> f32 {
+    DEFAULT_PHYSICAL_ROW_WIDTH
+}
+
+fn default_scene_fade_duration() -> usize {
+    DEFAULT_SCENE_FADE_DURATION
+}
+
+fn default_scene_fade_style() -> EaseStyle {
+    DEFAULT_SCENE_FADE_STYLE
+}
+
+/// Brightness levels and factors must be real numbers in the range `[0,1]`
+pub fn check_unit_range(name: &str, value: f32) -> Result<(), String> {
+    if value.is_finite() && (0. ..=1.).contains(&value) {
+        Ok(())
+    } else {
+        Err(format!("{name} must be in the range [0,1], got {value}"))
+    }
+}
+
 pub fn fixture_array_to_string(arr: &[usize; DEFAULT_COUNT]) -> String {
     let mut s = String::from("");
     arr.iter().enumerate().for_each(|(i, x)| {
@@ -306,7 +987,7 @@ pub fn fixture_string_to_array(s: &str) -> Result<[usize; DEFAULT_COUNT], ()> {
 // TODO: seems tedious to have to re-write all these enums
 // but Box<dyn Tween<f32>> is difficult to impl PartialEQ for
 // so UI / ComboBox is difficult
-#[derive(PartialEq, Debug, EnumIter, Display, Serialize, Deserialize)]
+#[derive(PartialEq, Clone, Copy, Debug, EnumIter, Display, Serialize, Deserialize)]
 pub enum EaseStyle {
     Linear,
     BounceIn,
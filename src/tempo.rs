@@ -0,0 +1,133 @@
+use log::info;
+use nannou::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
+
+/// Taps further apart than this (ms) start a new tap-tempo count
+const TAP_TIMEOUT: u64 = 2000;
+
+/// How many of the latest taps to average the tempo over
+const MAX_TAPS: usize = 5;
+
+pub const MIN_BPM: f32 = 20.;
+pub const MAX_BPM: f32 = 300.;
+
+/// Where triggers can land on the tempo clock
+#[derive(PartialEq, Clone, Copy, Debug, EnumIter, Display, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Quantise {
+    /// Trigger straight away
+    Off,
+    Beat,
+    HalfBeat,
+    QuarterBeat,
+}
+
+impl Quantise {
+    /// Length of the step in beats, if quantising
+    fn beats(self) -> Option<f64> {
+        match self {
+            Quantise::Off => None,
+            Quantise::Beat => Some(1.),
+            Quantise::HalfBeat => Some(0.5),
+            Quantise::QuarterBeat => Some(0.25),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct TempoSettings {
+    pub bpm: f32,
+    /// Hold triggers back until the next beat or subdivision, unless the
+    /// message says otherwise
+    pub quantise: Quantise,
+}
+
+impl Default for TempoSettings {
+    fn default() -> Self {
+        TempoSettings {
+            bpm: 120.,
+            quantise: Quantise::Off,
+        }
+    }
+}
+
+/// Duration (ms) of a number of beats at this tempo; durations too long to
+/// represent saturate, and are caught by validation before they get here
+pub fn beats_to_ms(beats: f32, bpm: f32) -> usize {
+    (beats * 60000. / bpm)
+        .round()
+        .max(1.)
+        .to_usize()
+        .unwrap_or(usize::MAX)
+}
+
+/// The tempo clock, which moves on with the engine's frames
+#[derive(Default)]
+pub struct Tempo {
+    /// Beats since the clock started
+    beats: f64,
+    /// Time (ms) since the clock started
+    elapsed: u64,
+    /// Times (ms) of the latest taps, oldest first
+    taps: Vec<u64>,
+}
+
+impl Tempo {
+    pub fn update(&mut self, settings: &TempoSettings, delta_time: usize) {
+        self.elapsed += delta_time.to_u64().unwrap();
+        self.beats += delta_time.to_f64().unwrap() * f64::from(settings.bpm) / 60000.;
+    }
+
+    /// Whole beats since the clock started
+    pub fn beat(&self) -> u64 {
+        self.beats.floor().to_u64().unwrap()
+    }
+
+    /// How far through the current beat, 0-1
+    pub fn phase(&self) -> f32 {
+        self.beats.fract().to_f32().unwrap()
+    }
+
+    /// Time (ms) until the next step of the given quantise setting; 0 if
+    /// not quantising, or if the clock is right on a step
+    pub fn until_next(&self, settings: &TempoSettings, quantise: Quantise) -> i64 {
+        match quantise.beats() {
+            None => 0,
+            Some(step) => {
+                let remaining = (step - self.beats % step) % step;
+                (remaining * 60000. / f64::from(settings.bpm))
+                    .round()
+                    .to_i64()
+                    .unwrap()
+            }
+        }
+    }
+
+    /// Register a tap of the tempo. Once there are two taps close enough
+    /// together, the BPM follows the average gap between them and the
+    /// clock jumps so that the tap lands on a beat.
+    pub fn tap(&mut self, settings: &mut TempoSettings) {
+        if self
+            .taps
+            .last()
+            .is_some_and(|last| self.elapsed - last > TAP_TIMEOUT)
+        {
+            self.taps.clear();
+        }
+        self.taps.push(self.elapsed);
+        if self.taps.len() > MAX_TAPS {
+            self.taps.remove(0);
+        }
+        if let (Some(first), Some(last)) = (self.taps.first(), self.taps.last()) {
+            let gaps = self.taps.len() - 1;
+            if gaps > 0 {
+                let average = (last - first).to_f32().unwrap() / gaps.to_f32().unwrap();
+                settings.bpm = (60000. / average.max(1.)).clamp(MIN_BPM, MAX_BPM);
+                info!("Tap tempo: {:.1} BPM", settings.bpm);
+            }
+        }
+        self.beats = self.beats.round();
+    }
+}
@@ -1,42 +1,886 @@
-use std::{net::IpAddr, process, time::Duration};
+use std::{
+    fmt,
+    net::IpAddr,
+    process,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use log::{debug, error, info};
+use clap::ValueEnum;
+use log::{debug, error, info, warn};
 use mqtt::{Client, Message, Receiver};
+use nannou::prelude::ToPrimitive;
 use paho_mqtt as mqtt;
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-const INPUT_TOPICS: &[&str] = &["+/+/lightTriggers", "+/+/lightReset"];
-const INPUT_QOS: &[i32; INPUT_TOPICS.len()] = &[2, 2];
+use crate::animation::LayerOptions;
+use crate::debounce::DebouncePolicy;
+use crate::effects::{ChaseDirection, EffectKind};
+use crate::merge::MergePolicy;
+use crate::profiles::ProfileList;
+use crate::settings::{check_unit_range, EaseStyle, FixtureGroup, Settings, DEFAULT_COUNT};
+use crate::tempo::{beats_to_ms, Quantise, MIN_BPM};
+
+const INPUT_PLUGS: &[&str] = &[
+    "lightTriggers",
+    "lightBatchTriggers",
+    "lightPositionTriggers",
+    "lightReset",
+    "lightEffects",
+    "settingsGet",
+    "settingsPatch",
+    "settingsSave",
+    "settingsLoad",
+    "settingsProfile",
+    "cues",
+    "scenes",
+];
+const INPUT_QOS: i32 = 2;
+
+const SETTINGS_OUTPUT_PLUG: &str = "settings";
+const PROFILES_OUTPUT_PLUG: &str = "profiles";
+const ERRORS_OUTPUT_PLUG: &str = "errors";
+
+/// Longest duration (ms) accepted for any single animation phase or delay
+const MAX_MESSAGE_DURATION: usize = 10 * 60 * 1000;
+
+/// Furthest ahead (ms) a message can be scheduled
+const MAX_SCHEDULE_AHEAD: u64 = 24 * 60 * 60 * 1000;
 
 pub struct TetherAgent {
     client: Client,
     receiver: Receiver<Option<Message>>,
+    encodings: PlugEncodings,
+    input_filter: InputFilter,
+    role: String,
+    id: String,
+    /// Group ids to publish on instead of `id`, one per installation, if
+    /// there are several
+    output_groups: Vec<String>,
 }
 
-#[derive(Deserialize, Debug)]
+/// Which agents are allowed to send messages to the input plugs. Topics are
+/// `role/id/plugName`; use "+" to accept any role or id. With several
+/// ids, each is an installation of its own (see `Installations`).
+pub struct InputFilter {
+    pub role: String,
+    pub ids: Vec<String>,
+}
+
+impl InputFilter {
+    fn topics(&self) -> Vec<String> {
+        self.ids
+            .iter()
+            .flat_map(|id| {
+                INPUT_PLUGS
+                    .iter()
+                    .map(move |plug_name| format!("{}/{}/{}", self.role, id, plug_name))
+            })
+            .collect()
+    }
+
+    fn accepts(&self, role: &str, id: &str) -> bool {
+        (self.role == "+" || self.role == role)
+            && self
+                .ids
+                .iter()
+                .any(|accepted| accepted == "+" || accepted == id)
+    }
+}
+
+/// How the payload of messages arriving on an input plug is encoded
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum PayloadEncoding {
+    /// Detect per message: payloads starting with `{` or `[` are JSON,
+    /// anything else is MessagePack
+    Auto,
+    #[value(name = "msgpack")]
+    MessagePack,
+    Json,
+}
+
+/// The encoding to use for every input plug, with optional per-plug overrides
+pub struct PlugEncodings {
+    pub default: PayloadEncoding,
+    pub overrides: Vec<(String, PayloadEncoding)>,
+}
+
+impl PlugEncodings {
+    pub fn for_plug(&self, plug_name: &str) -> PayloadEncoding {
+        self.overrides
+            .iter()
+            .find(|(name, _)| name == plug_name)
+            .map(|(_, encoding)| *encoding)
+            .unwrap_or(self.default)
+    }
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    Empty,
+    Json(serde_json::Error),
+    MessagePack(rmp_serde::decode::Error),
+    Neither {
+        json: serde_json::Error,
+        msgpack: rmp_serde::decode::Error,
+    },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Empty => write!(f, "payload is empty"),
+            DecodeError::Json(e) => write!(f, "invalid JSON payload: {e}"),
+            DecodeError::MessagePack(e) => write!(f, "invalid MessagePack payload: {e}"),
+            DecodeError::Neither { json, msgpack } => write!(
+                f,
+                "payload is neither valid JSON ({json}) nor valid MessagePack ({msgpack})"
+            ),
+        }
+    }
+}
+
+/// Parse a `<plug>=<encoding>` pair from the command line, e.g. `lightTriggers=json`
+pub fn parse_plug_encoding(s: &str) -> Result<(String, PayloadEncoding), String> {
+    let (plug_name, encoding) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected <plug>=<encoding>, got '{s}'"))?;
+    let encoding = PayloadEncoding::from_str(encoding, true)?;
+    Ok((String::from(plug_name), encoding))
+}
+
+fn looks_like_json(payload: &[u8]) -> bool {
+    matches!(
+        payload.iter().find(|b| !b.is_ascii_whitespace()),
+        Some(b'{') | Some(b'[')
+    )
+}
+
+/// MessagePack arrays (fixarray, array 16, array 32), which is how rmp_serde
+/// encodes structs by default
+fn is_msgpack_array(payload: &[u8]) -> bool {
+    matches!(payload.first(), Some(0x90..=0x9f) | Some(0xdc) | Some(0xdd))
+}
+
+pub fn decode_payload<T: DeserializeOwned>(
+    payload: &[u8],
+    encoding: PayloadEncoding,
+) -> Result<T, DecodeError> {
+    if payload.is_empty() {
+        return Err(DecodeError::Empty);
+    }
+    match encoding {
+        PayloadEncoding::Json => serde_json::from_slice(payload).map_err(DecodeError::Json),
+        PayloadEncoding::MessagePack => {
+            rmp_serde::from_slice(payload).map_err(DecodeError::MessagePack)
+        }
+        PayloadEncoding::Auto => {
+            // Try the likely encoding first, but fall back to the other one
+            // so that an unusual (but valid) payload is never rejected
+            let json_first = looks_like_json(payload);
+            let first = if json_first {
+                decode_payload(payload, PayloadEncoding::Json)
+            } else {
+                decode_payload(payload, PayloadEncoding::MessagePack)
+            };
+            match first {
+                Ok(parsed) => Ok(parsed),
+                Err(first_error) => {
+                    let second = if json_first {
+                        decode_payload(payload, PayloadEncoding::MessagePack)
+                    } else {
+                        decode_payload(payload, PayloadEncoding::Json)
+                    };
+                    match (second, first_error) {
+                        (Ok(parsed), _) => Ok(parsed),
+                        (Err(DecodeError::Json(json)), DecodeError::MessagePack(msgpack))
+                        | (Err(DecodeError::MessagePack(msgpack)), DecodeError::Json(json)) => {
+                            Err(DecodeError::Neither { json, msgpack })
+                        }
+                        (Err(_), first_error) => Err(first_error),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct LightTriggerMessage {
     pub id: usize,
     pub target_brightness: f32,
+    #[serde(flatten)]
+    pub options: TriggerOptions,
+    #[serde(flatten)]
+    pub schedule: ScheduleOptions,
+}
+
+/// Optionally run a message later instead of as soon as it is received;
+/// if both are given, `executeAt` wins
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleOptions {
+    /// Absolute time to run the message, in ms since the Unix epoch
+    pub execute_at: Option<u64>,
+    /// Run the message this many ms after it is received
+    pub execute_in: Option<u64>,
+}
+
+/// Optional overrides of the envelope and transmission settings,
+/// shared by all the trigger message types
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TriggerOptions {
     pub attack_duration: Option<usize>,
     pub release_duration: Option<usize>,
+    /// Attack duration in beats of the tempo clock, if not given in ms
+    pub attack_beats: Option<f32>,
+    /// Release duration in beats of the tempo clock, if not given in ms
+    pub release_beats: Option<f32>,
+    pub attack_style: Option<EaseStyle>,
+    pub release_style: Option<EaseStyle>,
+    /// Hold the trigger back until the next beat or subdivision
+    pub quantise: Option<Quantise>,
     pub final_brightness: Option<f32>,
     pub transmission_range: Option<f32>,
     pub transmission_delay: Option<i64>,
+    /// Debounce window (ms) for the triggered fixtures
+    pub debounce: Option<u64>,
+    pub debounce_policy: Option<DebouncePolicy>,
+    /// How the triggered fixtures merge with their current animation
+    pub merge: Option<MergePolicy>,
+    /// How neighbours reached by transmission merge with their current animation
+    pub transmission_merge: Option<MergePolicy>,
+    /// Play the triggered fixtures' animation in a layer of its own
+    pub layer: Option<LayerOptions>,
+    /// Play transmission to neighbours in layers of their own
+    pub transmission_layer: Option<LayerOptions>,
 }
 
-#[derive(Deserialize, Debug)]
+/// Activate several fixtures together, in the same frame. Fixtures can be
+/// listed individually (with an optional delay and brightness each), as an
+/// inclusive range, and/or as a named group from the settings.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LightBatchTriggerMessage {
+    #[serde(default)]
+    pub ids: Vec<BatchEntry>,
+    pub range: Option<IdRange>,
+    pub group: Option<String>,
+    pub target_brightness: f32,
+    #[serde(flatten)]
+    pub options: TriggerOptions,
+    #[serde(flatten)]
+    pub schedule: ScheduleOptions,
+}
+
+/// Activate the fixtures around a position along the row, e.g. where a
+/// visitor was detected, without needing to know the fixture order
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LightPositionTriggerMessage {
+    /// Distance from the left end of the row of fixtures
+    pub x: f32,
+    #[serde(default)]
+    pub units: PositionUnits,
+    #[serde(default)]
+    pub mode: PositionMode,
+    pub target_brightness: f32,
+    #[serde(flatten)]
+    pub options: TriggerOptions,
+    #[serde(flatten)]
+    pub schedule: ScheduleOptions,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum PositionUnits {
+    /// 0 is the left end of the row, 1 is the right end
+    #[default]
+    Normalised,
+    /// Real-world distance; the row is `physicalRowWidth` metres long
+    Metres,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum PositionMode {
+    /// Activate the fixture nearest to the position, transmitting to its
+    /// neighbours from the position itself
+    #[default]
+    Nearest,
+    /// Only transmit energy outwards from the exact position
+    Point,
+}
+
+/// Either a plain id, or an id with its own delay and/or brightness
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(untagged)]
+pub enum BatchEntry {
+    Id(usize),
+    #[serde(rename_all = "camelCase")]
+    Detailed {
+        id: usize,
+        delay: Option<i64>,
+        target_brightness: Option<f32>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct IdRange {
+    pub from: usize,
+    pub to: usize,
+}
+
+/// A single fixture to activate as part of a batch
+pub struct BatchTarget {
+    pub id: usize,
+    pub delay: Option<i64>,
+    pub target_brightness: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct LightResetMessage {
     #[serde(default)]
     pub target_brightness: Option<f32>,
     #[serde(default)]
     pub fade_duration: Option<usize>,
+    /// Curve of the fade; linear unless given
+    #[serde(default)]
+    pub fade_style: Option<EaseStyle>,
+    #[serde(flatten)]
+    pub schedule: ScheduleOptions,
+}
+
+/// Start or stop a procedural effect
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LightEffectMessage {
+    pub command: EffectCommand,
+    /// Required to start; when stopping, only stop this effect
+    pub effect: Option<EffectKind>,
+    #[serde(flatten)]
+    pub params: EffectParams,
+    #[serde(flatten)]
+    pub schedule: ScheduleOptions,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum EffectCommand {
+    Start,
+    Stop,
+}
+
+/// Optional overrides of the effect settings
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct EffectParams {
+    pub level: Option<f32>,
+    pub speed: Option<f32>,
+    pub direction: Option<ChaseDirection>,
+    pub width: Option<f32>,
+    pub wavelength: Option<f32>,
+    pub rate: Option<f32>,
+    /// Stop after this long (ms), instead of running until stopped
+    pub duration: Option<u64>,
+}
+
+/// Control the cue-list sequencer
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CueCommand {
+    pub command: CueOperation,
+    /// For `go`, fire this cue instead of the one on standby;
+    /// for `jump`, the cue to put on standby
+    pub cue: Option<CueRef>,
+    #[serde(flatten)]
+    pub schedule: ScheduleOptions,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum CueOperation {
+    Go,
+    Stop,
+    Pause,
+    Resume,
+    Jump,
+}
+
+/// A cue, by name or by its (zero-based) position in the cue list
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum CueRef {
+    Index(usize),
+    Name(String),
+}
+
+/// Recall, capture or delete a named scene
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SceneCommand {
+    pub command: SceneOperation,
+    pub name: String,
+    /// Crossfade time (ms) for `recall`, instead of the default from the settings
+    pub fade_duration: Option<usize>,
+    /// Crossfade curve for `recall`, instead of the default from the settings
+    #[serde(default)]
+    pub fade_style: Option<EaseStyle>,
+    #[serde(flatten)]
+    pub schedule: ScheduleOptions,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SceneOperation {
+    Recall,
+    Capture,
+    Delete,
+}
+
+/// Published on the errors plug whenever an incoming message is rejected
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageError {
+    pub plug: String,
+    pub reason: String,
+}
+
+pub fn check_duration(name: &str, value: Option<usize>) -> Result<(), String> {
+    match value {
+        Some(duration) if duration > MAX_MESSAGE_DURATION => Err(format!(
+            "{name} must be at most {MAX_MESSAGE_DURATION}ms, got {duration}"
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn check_delay(name: &str, value: Option<i64>) -> Result<(), String> {
+    match value {
+        Some(delay) if delay < 0 => Err(format!("{name} must not be negative, got {delay}")),
+        Some(delay) => check_duration(name, delay.to_usize()),
+        None => Ok(()),
+    }
+}
+
+/// Messages check every field against the ranges the animation code can handle.
+/// Whether ids refer to existing fixtures is checked when the message is
+/// applied, since that depends on the current layout.
+pub trait Validate {
+    fn validate(&self) -> Result<(), String>;
+}
+
+impl Validate for TriggerOptions {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(final_brightness) = self.final_brightness {
+            check_unit_range("finalBrightness", final_brightness)?;
+        }
+        check_duration("attackDuration", self.attack_duration)?;
+        check_duration("releaseDuration", self.release_duration)?;
+        for (name, beats) in [
+            ("attackBeats", self.attack_beats),
+            ("releaseBeats", self.release_beats),
+        ] {
+            if let Some(beats) = beats {
+                if !(beats.is_finite() && beats > 0.) {
+                    return Err(format!("{name} must be a positive number, got {beats}"));
+                }
+                // As long as it could be, at the slowest tempo
+                check_duration(name, Some(beats_to_ms(beats, MIN_BPM)))?;
+            }
+        }
+        if let Some(range) = self.transmission_range {
+            check_unit_range("transmissionRange", range)?;
+        }
+        check_delay("transmissionDelay", self.transmission_delay)?;
+        check_duration("debounce", self.debounce.and_then(|d| d.to_usize()))
+    }
+}
+
+impl Validate for ScheduleOptions {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(delay) = self.execute_in {
+            if delay > MAX_SCHEDULE_AHEAD {
+                return Err(format!(
+                    "executeIn must be at most {MAX_SCHEDULE_AHEAD}ms, got {delay}"
+                ));
+            }
+        }
+        if let Some(at) = self.execute_at {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0);
+            if at > now + MAX_SCHEDULE_AHEAD {
+                return Err(format!(
+                    "executeAt must be at most {MAX_SCHEDULE_AHEAD}ms in the future, got {at} (now is {now})"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Validate for LightTriggerMessage {
+    fn validate(&self) -> Result<(), String> {
+        check_unit_range("targetBrightness", self.target_brightness)?;
+        self.options.validate()?;
+        self.schedule.validate()
+    }
+}
+
+impl Validate for LightBatchTriggerMessage {
+    fn validate(&self) -> Result<(), String> {
+        if self.ids.is_empty() && self.range.is_none() && self.group.is_none() {
+            return Err(String::from(
+                "batch must specify at least one of ids, range or group",
+            ));
+        }
+        if let Some(IdRange { from, to }) = self.range {
+            if from > to {
+                return Err(format!(
+                    "range.from ({from}) must not be after range.to ({to})"
+                ));
+            }
+            if to >= DEFAULT_COUNT {
+                return Err(format!(
+                    "range.to must be at most {}, got {to}",
+                    DEFAULT_COUNT - 1
+                ));
+            }
+        }
+        check_unit_range("targetBrightness", self.target_brightness)?;
+        for entry in &self.ids {
+            if let BatchEntry::Detailed {
+                delay,
+                target_brightness,
+                ..
+            } = entry
+            {
+                check_delay("ids[].delay", *delay)?;
+                if let Some(target_brightness) = target_brightness {
+                    check_unit_range("ids[].targetBrightness", *target_brightness)?;
+                }
+            }
+        }
+        self.options.validate()?;
+        self.schedule.validate()
+    }
+}
+
+impl Validate for LightPositionTriggerMessage {
+    fn validate(&self) -> Result<(), String> {
+        match self.units {
+            PositionUnits::Normalised => check_unit_range("x", self.x)?,
+            PositionUnits::Metres => {
+                if !self.x.is_finite() || self.x < 0. {
+                    return Err(format!(
+                        "x must be a distance in metres from the left end of the row, got {}",
+                        self.x
+                    ));
+                }
+            }
+        }
+        check_unit_range("targetBrightness", self.target_brightness)?;
+        self.options.validate()?;
+        self.schedule.validate()
+    }
+}
+
+impl LightBatchTriggerMessage {
+    /// All the fixtures to activate: listed ids first, then the range, then the group
+    pub fn entries(&self, groups: &[FixtureGroup]) -> Result<Vec<BatchTarget>, String> {
+        let mut entries: Vec<BatchTarget> = self
+            .ids
+            .iter()
+            .map(|entry| match *entry {
+                BatchEntry::Id(id) => BatchTarget {
+                    id,
+                    delay: None,
+                    target_brightness: None,
+                },
+                BatchEntry::Detailed {
+                    id,
+                    delay,
+                    target_brightness,
+                } => BatchTarget {
+                    id,
+                    delay,
+                    target_brightness,
+                },
+            })
+            .collect();
+        let plain = |id| BatchTarget {
+            id,
+            delay: None,
+            target_brightness: None,
+        };
+        if let Some(IdRange { from, to }) = self.range {
+            entries.extend((from..=to).map(plain));
+        }
+        if let Some(name) = &self.group {
+            match groups.iter().find(|g| &g.name == name) {
+                Some(group) => entries.extend(group.ids.iter().copied().map(plain)),
+                None => return Err(format!("no fixture group named \"{name}\"")),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+impl Validate for LightEffectMessage {
+    fn validate(&self) -> Result<(), String> {
+        if self.command == EffectCommand::Start && self.effect.is_none() {
+            return Err(String::from("start needs an effect"));
+        }
+        let params = &self.params;
+        if let Some(level) = params.level {
+            check_unit_range("level", level)?;
+        }
+        for (name, value) in [
+            ("speed", params.speed),
+            ("width", params.width),
+            ("wavelength", params.wavelength),
+            ("rate", params.rate),
+        ] {
+            if let Some(value) = value {
+                if !(value.is_finite() && value >= 0.) {
+                    return Err(format!("{name} must not be negative, got {value}"));
+                }
+            }
+        }
+        check_duration("duration", params.duration.and_then(|d| d.to_usize()))?;
+        self.schedule.validate()
+    }
+}
+
+impl Validate for CueCommand {
+    fn validate(&self) -> Result<(), String> {
+        if self.command == CueOperation::Jump && self.cue.is_none() {
+            return Err(String::from("jump needs a cue"));
+        }
+        self.schedule.validate()
+    }
+}
+
+impl Validate for SceneCommand {
+    fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err(String::from("name must not be empty"));
+        }
+        check_duration("fadeDuration", self.fade_duration)?;
+        self.schedule.validate()
+    }
+}
+
+impl Validate for ProfileCommand {
+    fn validate(&self) -> Result<(), String> {
+        match &self.name {
+            Some(name) if name.trim().is_empty() => Err(String::from("name must not be empty")),
+            None if self.command != ProfileOperation::List => {
+                Err(String::from("name is required, except to list"))
+            }
+            _ => check_duration("transition", self.transition.and_then(|t| t.to_usize())),
+        }
+    }
+}
+
+impl Validate for LightResetMessage {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(target_brightness) = self.target_brightness {
+            check_unit_range("targetBrightness", target_brightness)?;
+        }
+        check_duration("fadeDuration", self.fade_duration)?;
+        self.schedule.validate()
+    }
+}
+
+/// A trigger encoded as a MessagePack array, i.e. its fields in this order
+/// without names. Flattened fields can only be decoded from maps, so
+/// array payloads are decoded with this original layout instead; the
+/// newer optional fields need a map.
+#[derive(Deserialize, Debug)]
+struct ArrayTriggerMessage {
+    id: usize,
+    target_brightness: f32,
+    #[serde(default)]
+    attack_duration: Option<usize>,
+    #[serde(default)]
+    release_duration: Option<usize>,
+    #[serde(default)]
+    final_brightness: Option<f32>,
+    #[serde(default)]
+    transmission_range: Option<f32>,
+    #[serde(default)]
+    transmission_delay: Option<i64>,
+}
+
+impl Validate for ArrayTriggerMessage {
+    fn validate(&self) -> Result<(), String> {
+        LightTriggerMessage::from(self).validate()
+    }
+}
+
+impl From<&ArrayTriggerMessage> for LightTriggerMessage {
+    fn from(m: &ArrayTriggerMessage) -> Self {
+        LightTriggerMessage {
+            id: m.id,
+            target_brightness: m.target_brightness,
+            options: TriggerOptions {
+                attack_duration: m.attack_duration,
+                release_duration: m.release_duration,
+                final_brightness: m.final_brightness,
+                transmission_range: m.transmission_range,
+                transmission_delay: m.transmission_delay,
+                ..TriggerOptions::default()
+            },
+            schedule: ScheduleOptions::default(),
+        }
+    }
+}
+
+impl From<ArrayTriggerMessage> for LightTriggerMessage {
+    fn from(m: ArrayTriggerMessage) -> Self {
+        LightTriggerMessage::from(&m)
+    }
+}
+
+/// A reset encoded as a MessagePack array; see `ArrayTriggerMessage`
+#[derive(Deserialize, Debug)]
+struct ArrayResetMessage {
+    #[serde(default)]
+    target_brightness: Option<f32>,
+    #[serde(default)]
+    fade_duration: Option<usize>,
+}
+
+impl Validate for ArrayResetMessage {
+    fn validate(&self) -> Result<(), String> {
+        LightResetMessage::from(self).validate()
+    }
+}
+
+impl From<&ArrayResetMessage> for LightResetMessage {
+    fn from(m: &ArrayResetMessage) -> Self {
+        LightResetMessage {
+            target_brightness: m.target_brightness,
+            fade_duration: m.fade_duration,
+            fade_style: None,
+            schedule: ScheduleOptions::default(),
+        }
+    }
+}
+
+impl From<ArrayResetMessage> for LightResetMessage {
+    fn from(m: ArrayResetMessage) -> Self {
+        LightResetMessage::from(&m)
+    }
+}
+
+pub enum SettingsCommand {
+    /// Publish the current settings without changing anything
+    Get,
+    /// Change any subset of the settings fields
+    Patch(serde_json::Value),
+    Save,
+    Load,
+    Profile(ProfileCommand),
+}
+
+/// Switch between, save or delete named settings profiles, or just list them
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileCommand {
+    pub command: ProfileOperation,
+    /// Required except for `list`
+    pub name: Option<String>,
+    /// Time (ms) to interpolate the settings over when switching
+    pub transition: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ProfileOperation {
+    Switch,
+    Save,
+    Delete,
+    List,
 }
 
 pub enum LightMessages {
     Trigger(LightTriggerMessage),
+    BatchTrigger(LightBatchTriggerMessage),
+    PositionTrigger(LightPositionTriggerMessage),
     Reset(LightResetMessage),
+    Effect(LightEffectMessage),
+    Settings(SettingsCommand),
+    Cue(CueCommand),
+    Scene(SceneCommand),
+}
+
+impl LightMessages {
+    /// The input plug this kind of message arrives on
+    pub fn plug_name(&self) -> &'static str {
+        match self {
+            LightMessages::Trigger(_) => "lightTriggers",
+            LightMessages::BatchTrigger(_) => "lightBatchTriggers",
+            LightMessages::PositionTrigger(_) => "lightPositionTriggers",
+            LightMessages::Reset(_) => "lightReset",
+            LightMessages::Effect(_) => "lightEffects",
+            LightMessages::Settings(SettingsCommand::Get) => "settingsGet",
+            LightMessages::Settings(SettingsCommand::Patch(_)) => "settingsPatch",
+            LightMessages::Settings(SettingsCommand::Save) => "settingsSave",
+            LightMessages::Settings(SettingsCommand::Load) => "settingsLoad",
+            LightMessages::Settings(SettingsCommand::Profile(_)) => "settingsProfile",
+            LightMessages::Cue(_) => "cues",
+            LightMessages::Scene(_) => "scenes",
+        }
+    }
+
+    /// When the message should run, for the message types that can be scheduled
+    pub fn schedule(&self) -> Option<&ScheduleOptions> {
+        match self {
+            LightMessages::Trigger(m) => Some(&m.schedule),
+            LightMessages::BatchTrigger(m) => Some(&m.schedule),
+            LightMessages::PositionTrigger(m) => Some(&m.schedule),
+            LightMessages::Reset(m) => Some(&m.schedule),
+            LightMessages::Effect(m) => Some(&m.schedule),
+            LightMessages::Cue(m) => Some(&m.schedule),
+            LightMessages::Scene(m) => Some(&m.schedule),
+            LightMessages::Settings(_) => None,
+        }
+    }
+
+    /// The message as it would arrive on its plug, without any scheduling
+    /// (which has already been applied by the time a message runs).
+    /// Settings and cue commands give `None`: replaying settings changes is
+    /// not wanted, and the messages fired by cues are recorded themselves.
+    /// Likewise only scene recalls are recorded, not changes to the scenes file.
+    pub fn payload(&self) -> Option<serde_json::Value> {
+        let payload = match self {
+            LightMessages::Trigger(m) => serde_json::to_value(m),
+            LightMessages::BatchTrigger(m) => serde_json::to_value(m),
+            LightMessages::PositionTrigger(m) => serde_json::to_value(m),
+            LightMessages::Reset(m) => serde_json::to_value(m),
+            LightMessages::Effect(m) => serde_json::to_value(m),
+            LightMessages::Scene(m) if m.command == SceneOperation::Recall => {
+                serde_json::to_value(m)
+            }
+            LightMessages::Settings(_) | LightMessages::Cue(_) | LightMessages::Scene(_) => {
+                return None
+            }
+        };
+        let mut payload = payload.expect("Failed to serialise light message");
+        if let Some(fields) = payload.as_object_mut() {
+            fields.remove("executeAt");
+            fields.remove("executeIn");
+            // Leave out unset options, to keep it as close as possible to the original
+            fields.retain(|_, value| !value.is_null());
+        }
+        Some(payload)
+    }
 }
 
 impl TetherAgent {
@@ -44,7 +888,13 @@ impl TetherAgent {
         self.client.is_connected()
     }
 
-    pub fn new(tether_host: IpAddr) -> Self {
+    pub fn new(
+        tether_host: IpAddr,
+        role: &str,
+        id: &str,
+        input_filter: InputFilter,
+        encodings: PlugEncodings,
+    ) -> Self {
         let broker_uri = format!("tcp://{tether_host}:1883");
 
         let create_opts = mqtt::CreateOptionsBuilder::new()
@@ -58,7 +908,21 @@ impl TetherAgent {
         // Initialize the consumer before connecting
         let receiver = client.start_consuming();
 
-        TetherAgent { client, receiver }
+        TetherAgent {
+            client,
+            receiver,
+            encodings,
+            input_filter,
+            role: String::from(role),
+            id: String::from(id),
+            output_groups: Vec::new(),
+        }
+    }
+
+    /// Publish under these group ids (one per installation) instead of the
+    /// agent's own id
+    pub fn set_output_groups(&mut self, groups: Vec<String>) {
+        self.output_groups = groups;
     }
 
     pub fn connect(&mut self) {
@@ -75,7 +939,9 @@ impl TetherAgent {
         match self.client.connect(conn_opts) {
             Ok(res) => {
                 info!("Connected OK: {res:?}");
-                match self.client.subscribe_many(INPUT_TOPICS, INPUT_QOS) {
+                let topics = self.input_filter.topics();
+                let qos = vec![INPUT_QOS; topics.len()];
+                match self.client.subscribe_many(&topics, &qos) {
                     Ok(res) => {
                         debug!("Subscribe OK: {res:?}");
                     }
@@ -91,55 +957,169 @@ impl TetherAgent {
         }
     }
 
-    pub fn check_messages(&self) -> Option<LightMessages> {
-        if let Some(m) = self.receiver.try_iter().find_map(|m| m) {
-            let payload = m.payload().to_vec();
-
-            let plug_name = parse_plug_name(m.topic());
+    /// Parse every message received since the last check, in order of
+    /// arrival, with the id of the group each was sent by
+    pub fn check_messages(&self) -> Vec<(LightMessages, String)> {
+        self.receiver
+            .try_iter()
+            .flatten()
+            .filter_map(|m| self.parse_message(&m))
+            .collect()
+    }
 
-            match plug_name {
-                "lightTriggers" => {
-                    let light_message: Result<LightTriggerMessage, rmp_serde::decode::Error> =
-                        rmp_serde::from_slice(&payload);
+    fn parse_message(&self, m: &Message) -> Option<(LightMessages, String)> {
+        let payload = m.payload();
 
-                    match light_message {
-                        Ok(parsed) => {
-                            info!("Parsed LightTriggerMessage: {parsed:?}");
-                            if parsed.target_brightness.is_nan() {
-                                panic!("target_brightness should be a valid number");
-                            }
-                            Some(LightMessages::Trigger(parsed))
-                        }
-                        Err(e) => {
-                            error!("Failed to parse Light Reset message: {}", e);
-                            None
-                        }
-                    }
+        let (group, plug_name) = match parse_topic(m.topic()) {
+            Some((role, id, plug_name)) => {
+                if !self.input_filter.accepts(role, id) {
+                    debug!("Ignoring message from {role}/{id}");
+                    return None;
                 }
-                "lightReset" => {
-                    let light_message: Result<LightResetMessage, rmp_serde::decode::Error> =
-                        rmp_serde::from_slice(&payload);
-
-                    match light_message {
-                        Ok(parsed) => {
-                            info!("Parsed LightResetMessage: {parsed:?}");
-                            Some(LightMessages::Reset(parsed))
-                        }
-                        Err(e) => {
-                            error!("Failed to parse Light Reset message: {}", e);
-                            None
-                        }
-                    }
+                (id, plug_name)
+            }
+            None => {
+                warn!("Ignoring message on malformed topic \"{}\"", m.topic());
+                return None;
+            }
+        };
+        let encoding = self.encodings.for_plug(plug_name);
+
+        match decode_light_message(plug_name, payload, encoding) {
+            Ok(light_message) => light_message.map(|m| (m, String::from(group))),
+            Err(reason) => {
+                self.publish_error(plug_name, &reason, Some(group));
+                None
+            }
+        }
+    }
+
+    /// Publish on one of this agent's own plugs, i.e. `role/id/plugName`,
+    /// or `role/group/plugName` for one installation of several; always
+    /// encoded as MessagePack
+    fn publish<T: Serialize>(&self, plug_name: &str, data: &T, group: Option<&str>) {
+        if !self.is_connected() {
+            return;
+        }
+        let id = match group {
+            Some(group) if self.output_groups.iter().any(|g| g == group) => group,
+            _ => &self.id,
+        };
+        let topic = format!("{}/{}/{}", self.role, id, plug_name);
+        match rmp_serde::to_vec_named(data) {
+            Ok(payload) => {
+                if let Err(e) = self.client.publish(Message::new(&topic, payload, 1)) {
+                    error!("Failed to publish on {topic}: {e}");
                 }
-                _ => None,
             }
-        } else {
-            None
+            Err(e) => error!("Failed to encode message for {topic}: {e}"),
+        }
+    }
+
+    /// Publish for every installation, since they all share the settings
+    fn publish_all<T: Serialize>(&self, plug_name: &str, data: &T) {
+        if self.output_groups.is_empty() {
+            self.publish(plug_name, data, None);
+        }
+        for group in &self.output_groups {
+            self.publish(plug_name, data, Some(group));
         }
     }
+
+    pub fn publish_settings(&self, settings: &Settings) {
+        self.publish_all(SETTINGS_OUTPUT_PLUG, settings);
+    }
+
+    pub fn publish_profiles(&self, profiles: &ProfileList) {
+        self.publish_all(PROFILES_OUTPUT_PLUG, profiles);
+    }
+
+    /// Log a rejected message and publish the reason, on the topics of the
+    /// group it came from, so that the sender can find out what went wrong
+    pub fn publish_error(&self, plug_name: &str, reason: &str, group: Option<&str>) {
+        error!("Rejected message on plug \"{plug_name}\": {reason}");
+        self.publish(
+            ERRORS_OUTPUT_PLUG,
+            &MessageError {
+                plug: String::from(plug_name),
+                reason: String::from(reason),
+            },
+            group,
+        );
+    }
+}
+
+/// Decode and validate the payload of a message on one of the input plugs.
+/// Unknown plug names are not an error, they are just ignored (`Ok(None)`).
+pub fn decode_light_message(
+    plug_name: &str,
+    payload: &[u8],
+    encoding: PayloadEncoding,
+) -> Result<Option<LightMessages>, String> {
+    let light_message = match plug_name {
+        "lightTriggers" if is_msgpack_array(payload) && encoding != PayloadEncoding::Json => {
+            let parsed: ArrayTriggerMessage = decode_message(payload, encoding, "Light Trigger")?;
+            LightMessages::Trigger(parsed.into())
+        }
+        "lightTriggers" => {
+            LightMessages::Trigger(decode_message(payload, encoding, "Light Trigger")?)
+        }
+        "lightBatchTriggers" => {
+            LightMessages::BatchTrigger(decode_message(payload, encoding, "Light Batch Trigger")?)
+        }
+        "lightPositionTriggers" => LightMessages::PositionTrigger(decode_message(
+            payload,
+            encoding,
+            "Light Position Trigger",
+        )?),
+        "lightReset" if is_msgpack_array(payload) && encoding != PayloadEncoding::Json => {
+            let parsed: ArrayResetMessage = decode_message(payload, encoding, "Light Reset")?;
+            LightMessages::Reset(parsed.into())
+        }
+        "lightReset" => LightMessages::Reset(decode_message(payload, encoding, "Light Reset")?),
+        "lightEffects" => LightMessages::Effect(decode_message(payload, encoding, "Light Effect")?),
+        "settingsGet" => LightMessages::Settings(SettingsCommand::Get),
+        "settingsSave" => LightMessages::Settings(SettingsCommand::Save),
+        "settingsLoad" => LightMessages::Settings(SettingsCommand::Load),
+        "settingsProfile" => LightMessages::Settings(SettingsCommand::Profile(decode_message(
+            payload,
+            encoding,
+            "Settings Profile",
+        )?)),
+        "cues" => LightMessages::Cue(decode_message(payload, encoding, "Cue")?),
+        "scenes" => LightMessages::Scene(decode_message(payload, encoding, "Scene")?),
+        "settingsPatch" => {
+            let patch: serde_json::Value = decode_payload(payload, encoding)
+                .map_err(|e| format!("Failed to parse settings patch: {e}"))?;
+            info!("Parsed settings patch: {patch}");
+            LightMessages::Settings(SettingsCommand::Patch(patch))
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(light_message))
 }
 
-fn parse_plug_name(topic: &str) -> &str {
-    let parts: Vec<&str> = topic.split('/').collect();
-    parts[2]
+fn decode_message<T: DeserializeOwned + Validate + fmt::Debug>(
+    payload: &[u8],
+    encoding: PayloadEncoding,
+    description: &str,
+) -> Result<T, String> {
+    let parsed: T = decode_payload(payload, encoding)
+        .map_err(|e| format!("Failed to parse {description} message: {e}"))?;
+    info!("Parsed {description} message: {parsed:?}");
+    parsed.validate()?;
+    Ok(parsed)
+}
+
+/// Split a Tether topic into its `(role, id, plugName)` parts, if it has exactly three
+fn parse_topic(topic: &str) -> Option<(&str, &str, &str)> {
+    let mut parts = topic.split('/');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(role), Some(id), Some(plug_name), None)
+            if !role.is_empty() && !id.is_empty() && !plug_name.is_empty() =>
+        {
+            Some((role, id, plug_name))
+        }
+        _ => None,
+    }
 }
@@ -2,18 +2,35 @@ use std::time::Duration;
 
 use log::error;
 use nannou::prelude::*;
+use nannou::rand::rand::{rngs::StdRng, SeedableRng};
 use nannou_egui::egui::{self, ComboBox, Slider};
 
 use strum::IntoEnumIterator;
 
+use crate::ambient::{AmbientSettings, AmbientStyle};
+use crate::animation::{BlendMode, LayerOptions};
+use crate::audio::{AudioMapping, AudioSettings, PendingAudio};
+use crate::debounce::{DebouncePolicy, DebounceSettings};
+use crate::effects::{ChaseDirection, EffectKind, EffectSettings};
+use crate::jitter::JitterSettings;
+use crate::merge::{MergePolicy, MergeSettings};
+use crate::opening_hours::OpeningHoursSettings;
 use crate::particles::build_layout;
+use crate::physics::{BrightnessSource, PhysicsSettings};
+use crate::recording::{same_file, Player, Recorder};
+use crate::scheduler::{ExpiredPolicy, SchedulingSettings};
+use crate::sequencer::{Sequencer, SequencerState};
 use crate::settings::{
     fixture_array_to_string, fixture_string_to_array, EaseStyle, Model, PhaseSettings, Settings,
     TransmissionSettings, DEFAULT_WIDTH_RATIO,
 };
+use crate::tempo::{Quantise, TempoSettings, MAX_BPM, MIN_BPM};
+use crate::tether::EffectParams;
 
 pub fn build_ui(model: &mut Model, since_start: Duration, window_rect: Rect) {
-    let egui = &mut model.egui;
+    let Some(egui) = &mut model.egui else {
+        return;
+    };
 
     egui.set_elapsed_time(since_start);
     let ctx = egui.begin_frame();
@@ -27,8 +44,22 @@ pub fn build_ui(model: &mut Model, since_start: Duration, window_rect: Rect) {
             attack_settings,
             release_settings,
             transmission_settings,
+            transmission_layer,
             trigger_full_brightness,
             trigger_by_order,
+            physical_row_width,
+            scheduling,
+            debounce,
+            merge,
+            ambient,
+            effects,
+            opening_hours,
+            physics,
+            audio,
+            tempo,
+            jitter,
+            scene_fade_duration,
+            scene_fade_style,
             mouse_enable,
             mouse_brightness_value,
             resting_brightness,
@@ -46,6 +77,21 @@ pub fn build_ui(model: &mut Model, since_start: Duration, window_rect: Rect) {
         // ---------------- VIEW/INTERACTION SECTION
 
         ui.collapsing("View / Interaction", |ui| {
+            let installations = &mut model.installations;
+            if installations.len() > 1 {
+                let name = |index| installations.id(index).unwrap_or_default().to_string();
+                let mut selected = installations.selected;
+                ComboBox::from_label("Installation (group id)")
+                    .selected_text(name(selected))
+                    .show_ui(ui, |ui| {
+                        for index in 0..installations.len() {
+                            ui.selectable_value(&mut selected, index, name(index));
+                        }
+                    });
+                installations.selected = selected;
+                ui.separator();
+            }
+
             ui.horizontal(|ui| {
                 ui.label("Chimes count:");
                 ui.add(Slider::new(chimes_count, 1..=30));
@@ -56,7 +102,13 @@ pub fn build_ui(model: &mut Model, since_start: Duration, window_rect: Rect) {
                         window_rect.w() * DEFAULT_WIDTH_RATIO,
                         window_rect.h() * 0.2,
                         fixture_order,
-                    )
+                    );
+                    model.installations.lay_out_parked(
+                        current_count,
+                        window_rect.w() * DEFAULT_WIDTH_RATIO,
+                        window_rect.h() * 0.2,
+                        fixture_order,
+                    );
                 }
             });
 
@@ -86,11 +138,22 @@ pub fn build_ui(model: &mut Model, since_start: Duration, window_rect: Rect) {
         // ---------------- ANIMATION SECTION
 
         ui.collapsing("Animation", |ui| {
-            let PhaseSettings { duration, style } = attack_settings;
+            let PhaseSettings {
+                duration,
+                style,
+                beats,
+            } = attack_settings;
 
             ui.horizontal(|ui| {
                 ui.label("Attack duration:");
-                ui.add(Slider::new(duration, 1..=10000).suffix("ms"));
+                match beats {
+                    Some(beats) => ui.add(Slider::new(beats, 0.25..=16.).suffix(" beats")),
+                    None => ui.add(Slider::new(duration, 1..=10000).suffix("ms")),
+                };
+                let mut in_beats = beats.is_some();
+                if ui.checkbox(&mut in_beats, "in beats").changed() {
+                    *beats = in_beats.then_some(1.);
+                }
             });
 
             ComboBox::from_label("Attack-phase Tween")
@@ -102,13 +165,24 @@ pub fn build_ui(model: &mut Model, since_start: Duration, window_rect: Rect) {
                     }
                 });
 
-            let PhaseSettings { duration, style } = release_settings;
+            let PhaseSettings {
+                duration,
+                style,
+                beats,
+            } = release_settings;
 
             ui.separator();
 
             ui.horizontal(|ui| {
                 ui.label("Release duration:");
-                ui.add(Slider::new(duration, 1..=10000).suffix("ms"));
+                match beats {
+                    Some(beats) => ui.add(Slider::new(beats, 0.25..=16.).suffix(" beats")),
+                    None => ui.add(Slider::new(duration, 1..=10000).suffix("ms")),
+                };
+                let mut in_beats = beats.is_some();
+                if ui.checkbox(&mut in_beats, "in beats").changed() {
+                    *beats = in_beats.then_some(1.);
+                }
             });
 
             ComboBox::from_label("Release-phase Tween")
@@ -141,6 +215,368 @@ pub fn build_ui(model: &mut Model, since_start: Duration, window_rect: Rect) {
                 ui.label("Transmission max delay");
                 ui.add(Slider::new(max_delay, 0..=4000).suffix("ms"))
             });
+
+            let mut layered = transmission_layer.is_some();
+            if ui
+                .checkbox(&mut layered, "Transmission in its own layer")
+                .changed()
+            {
+                *transmission_layer = layered.then_some(LayerOptions {
+                    blend: BlendMode::Max,
+                    priority: 0,
+                });
+            }
+            if let Some(LayerOptions { blend, priority }) = transmission_layer {
+                ComboBox::from_label("Transmission layer blend")
+                    .selected_text(blend.to_string())
+                    .show_ui(ui, |ui| {
+                        for named_blend in BlendMode::iter() {
+                            let n = named_blend.to_string();
+                            ui.selectable_value(blend, named_blend, n);
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    ui.label("Transmission layer priority:");
+                    ui.add(Slider::new(priority, -10..=10));
+                });
+            }
+        });
+
+        // ---------------- JITTER SECTION
+
+        ui.collapsing("Jitter", |ui| {
+            let JitterSettings {
+                attack_duration,
+                release_duration,
+                brightness,
+                transmission_delay,
+            } = jitter;
+
+            ui.horizontal(|ui| {
+                ui.label("Attack duration ±");
+                ui.add(Slider::new(attack_duration, 0. ..=1.).suffix("x"));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Release duration ±");
+                ui.add(Slider::new(release_duration, 0. ..=1.).suffix("x"));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Brightness ±");
+                ui.add(Slider::new(brightness, 0. ..=1.));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Transmission delay ±");
+                ui.add(Slider::new(transmission_delay, 0..=2000).suffix("ms"));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label(format!("Random seed: {}", model.seed));
+                if ui.button("Restart sequence").clicked() {
+                    model.jitter_rng = StdRng::seed_from_u64(model.seed);
+                }
+            });
+        });
+
+        // ---------------- TEMPO SECTION
+
+        ui.collapsing("Tempo", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("BPM:");
+                ui.add(Slider::new(&mut tempo.bpm, MIN_BPM..=MAX_BPM));
+                if ui.button("Tap").clicked() {
+                    model.tempo.tap(tempo);
+                }
+            });
+
+            let TempoSettings { quantise, .. } = tempo;
+            ComboBox::from_label("Quantise triggers to")
+                .selected_text(quantise.to_string())
+                .show_ui(ui, |ui| {
+                    for named_quantise in Quantise::iter() {
+                        let n = named_quantise.to_string();
+                        ui.selectable_value(quantise, named_quantise, n);
+                    }
+                });
+
+            ui.horizontal(|ui| {
+                ui.label(format!("Beat {}", model.tempo.beat() + 1));
+                ui.add(egui::ProgressBar::new(1. - model.tempo.phase()).desired_width(60.));
+            });
+        });
+
+        // ---------------- AMBIENT SECTION
+
+        ui.collapsing("Ambient", |ui| {
+            let AmbientSettings {
+                enabled,
+                idle_timeout,
+                style,
+                level,
+                period,
+                fade_duration,
+                twinkle_rate,
+            } = ambient;
+
+            ui.checkbox(enabled, "Ambient layer when idle");
+
+            ui.horizontal(|ui| {
+                ui.label("Start after idle for:");
+                ui.add(Slider::new(idle_timeout, 1000..=600000).suffix("ms"));
+            });
+
+            ComboBox::from_label("Ambient style")
+                .selected_text(style.to_string())
+                .show_ui(ui, |ui| {
+                    for named_style in AmbientStyle::iter() {
+                        let n = named_style.to_string();
+                        ui.selectable_value(style, named_style, n);
+                    }
+                });
+
+            ui.horizontal(|ui| {
+                ui.label("Ambient brightness:");
+                ui.add(Slider::new(level, 0. ..=1.).suffix("x"));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Ambient period:");
+                ui.add(Slider::new(period, 500..=60000).suffix("ms"));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Fade in/out:");
+                ui.add(Slider::new(fade_duration, 0..=10000).suffix("ms"));
+            });
+
+            if *style == AmbientStyle::Twinkle {
+                ui.horizontal(|ui| {
+                    ui.label("Twinkles per second:");
+                    ui.add(Slider::new(twinkle_rate, 0. ..=10.));
+                });
+            }
+
+            if model.ambient.is_active() {
+                ui.label("Ambient layer running");
+            } else if let Some(starts_in) = model.ambient.starts_in(ambient) {
+                ui.label(format!(
+                    "Ambient layer starts in {:.0}s",
+                    starts_in.to_f32().unwrap() / 1000.
+                ));
+            }
+        });
+
+        // ---------------- EFFECTS SECTION
+
+        ui.collapsing("Effects", |ui| {
+            ComboBox::from_label("Effect")
+                .selected_text(model.effects.selected.to_string())
+                .show_ui(ui, |ui| {
+                    for kind in EffectKind::iter() {
+                        let n = kind.to_string();
+                        ui.selectable_value(&mut model.effects.selected, kind, n);
+                    }
+                });
+
+            let EffectSettings {
+                level,
+                speed,
+                direction,
+                width,
+                wavelength,
+                rate,
+                max_strobe_rate,
+                max_strobe_duration,
+            } = effects;
+
+            ui.horizontal(|ui| {
+                ui.label("Effect brightness:");
+                ui.add(Slider::new(level, 0. ..=1.).suffix("x"));
+            });
+
+            match model.effects.selected {
+                EffectKind::Chase => {
+                    ui.horizontal(|ui| {
+                        ui.label("Speed:");
+                        ui.add(Slider::new(speed, 0.1..=30.).suffix(" fixtures/s"));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Width:");
+                        ui.add(Slider::new(width, 0.5..=10.).suffix(" fixtures"));
+                    });
+                    ComboBox::from_label("Direction")
+                        .selected_text(direction.to_string())
+                        .show_ui(ui, |ui| {
+                            for named_direction in ChaseDirection::iter() {
+                                let n = named_direction.to_string();
+                                ui.selectable_value(direction, named_direction, n);
+                            }
+                        });
+                }
+                EffectKind::Wave | EffectKind::Noise => {
+                    ui.horizontal(|ui| {
+                        ui.label("Speed:");
+                        ui.add(Slider::new(speed, 0.01..=5.).suffix(" cycles/s"));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Wavelength:");
+                        ui.add(Slider::new(wavelength, 1. ..=30.).suffix(" fixtures"));
+                    });
+                }
+                EffectKind::Sparkle => {
+                    ui.horizontal(|ui| {
+                        ui.label("Sparkles per second:");
+                        ui.add(Slider::new(rate, 0. ..=30.));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Fade speed:");
+                        ui.add(Slider::new(speed, 0.1..=20.));
+                    });
+                }
+                EffectKind::Strobe => {
+                    ui.horizontal(|ui| {
+                        ui.label("Flashes per second:");
+                        ui.add(Slider::new(rate, 0. ..=*max_strobe_rate));
+                    });
+                    ui.label(format!(
+                        "Safety limits: at most {max_strobe_rate} flashes per second, for {:.0}s",
+                        max_strobe_duration.to_f32().unwrap() / 1000.
+                    ));
+                }
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("Start").clicked() {
+                    let kind = model.effects.selected;
+                    model.effects.start(kind, effects, &EffectParams::default());
+                    model.ambient.notice_activity();
+                }
+                if ui.button("Stop").clicked() {
+                    model.effects.stop(None);
+                }
+            });
+
+            if let Some(running) = model.effects.running() {
+                ui.label(format!("Running: {running}"));
+            }
+        });
+
+        // ---------------- PHYSICS SECTION
+
+        ui.collapsing("Physics", |ui| {
+            let PhysicsSettings {
+                enabled,
+                stiffness,
+                damping,
+                coupling,
+                impulse,
+                max_angle,
+                source,
+            } = physics;
+
+            ui.checkbox(enabled, "Swinging chimes (triggers push, not animate)");
+
+            ComboBox::from_label("Brightness from")
+                .selected_text(source.to_string())
+                .show_ui(ui, |ui| {
+                    for named_source in BrightnessSource::iter() {
+                        let n = named_source.to_string();
+                        ui.selectable_value(source, named_source, n);
+                    }
+                });
+
+            ui.horizontal(|ui| {
+                ui.label("Stiffness:");
+                ui.add(Slider::new(stiffness, 1. ..=100.));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Damping:");
+                ui.add(Slider::new(damping, 0. ..=5.).suffix("/s"));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Coupling to neighbours:");
+                ui.add(Slider::new(coupling, 0. ..=50.));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Impulse at full brightness:");
+                ui.add(Slider::new(impulse, 0. ..=10.).suffix("rad/s"));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Swing for full brightness:");
+                ui.add(Slider::new(max_angle, 0.05..=1.5).suffix("rad"));
+            });
+        });
+
+        // ---------------- OPENING HOURS SECTION
+
+        ui.collapsing("Opening Hours", |ui| {
+            let OpeningHoursSettings {
+                enabled,
+                weekdays,
+                dimming,
+                holidays,
+                fade_duration,
+            } = opening_hours;
+
+            ui.checkbox(enabled, "Follow opening hours");
+
+            ui.horizontal(|ui| {
+                ui.label("Fade when opening/closing:");
+                ui.add(Slider::new(fade_duration, 0..=60000).suffix("ms"));
+            });
+
+            ui.label(format!(
+                "{} (master brightness {:.0}%)",
+                model.opening_hours.state(),
+                model.opening_hours.master() * 100.
+            ));
+
+            let days = [
+                ("Mon", &weekdays.mon),
+                ("Tue", &weekdays.tue),
+                ("Wed", &weekdays.wed),
+                ("Thu", &weekdays.thu),
+                ("Fri", &weekdays.fri),
+                ("Sat", &weekdays.sat),
+                ("Sun", &weekdays.sun),
+            ];
+            for (day, windows) in days {
+                let hours = if windows.is_empty() {
+                    String::from("closed")
+                } else {
+                    windows
+                        .iter()
+                        .map(|w| w.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                ui.label(format!("{day}: {hours}"));
+            }
+            for period in dimming.iter() {
+                ui.label(format!(
+                    "Dim to {:.0}% from {} to {}",
+                    period.brightness * 100.,
+                    period.from,
+                    period.to
+                ));
+            }
+            if !holidays.is_empty() {
+                ui.label(format!(
+                    "Holidays: {}",
+                    holidays
+                        .iter()
+                        .map(|d| d.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            ui.label("Edit hours, dimming and holidays in the settings file");
         });
 
         // ---------------- REMOTE CONTROL SECTION
@@ -151,6 +587,400 @@ pub fn build_ui(model: &mut Model, since_start: Duration, window_rect: Rect) {
                 "Remote trigger max brightness always",
             );
             ui.checkbox(trigger_by_order, "Remote trigger by order not #ID");
+            ui.horizontal(|ui| {
+                ui.label("Physical row width:");
+                ui.add(Slider::new(physical_row_width, 0.5..=50.).suffix("m"));
+            });
+
+            ui.separator();
+
+            let DebounceSettings { window, policy } = debounce;
+            ui.horizontal(|ui| {
+                ui.label("Debounce window:");
+                ui.add(Slider::new(window, 0..=2000).suffix("ms"));
+            });
+            ComboBox::from_label("Duplicate triggers")
+                .selected_text(policy.to_string())
+                .show_ui(ui, |ui| {
+                    for named_policy in DebouncePolicy::iter() {
+                        let n = named_policy.to_string();
+                        ui.selectable_value(policy, named_policy, n);
+                    }
+                });
+
+            ui.separator();
+
+            let MergeSettings {
+                direct,
+                transmitted,
+            } = merge;
+            ComboBox::from_label("Trigger on an animating fixture")
+                .selected_text(direct.to_string())
+                .show_ui(ui, |ui| {
+                    for named_policy in MergePolicy::iter() {
+                        let n = named_policy.to_string();
+                        ui.selectable_value(direct, named_policy, n);
+                    }
+                });
+            ComboBox::from_label("Transmission to an animating fixture")
+                .selected_text(transmitted.to_string())
+                .show_ui(ui, |ui| {
+                    for named_policy in MergePolicy::iter() {
+                        let n = named_policy.to_string();
+                        ui.selectable_value(transmitted, named_policy, n);
+                    }
+                });
+
+            ui.separator();
+
+            let SchedulingSettings {
+                late_tolerance,
+                expired_policy,
+            } = scheduling;
+
+            ui.horizontal(|ui| {
+                ui.label("Late message tolerance:");
+                ui.add(Slider::new(late_tolerance, 0..=2000).suffix("ms"));
+            });
+
+            ComboBox::from_label("Messages later than that")
+                .selected_text(expired_policy.to_string())
+                .show_ui(ui, |ui| {
+                    for policy in ExpiredPolicy::iter() {
+                        let n = policy.to_string();
+                        ui.selectable_value(expired_policy, policy, n);
+                    }
+                });
+
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "Scheduled messages waiting: {}",
+                    model.scheduler.len()
+                ));
+                if ui.button("Clear").clicked() {
+                    model.scheduler.clear();
+                }
+            });
+        });
+
+        // ---------------- RECORDING SECTION
+        ui.collapsing("Recording / Playback", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("File:");
+                ui.text_edit_singleline(&mut model.recording_path);
+            });
+
+            match &model.recorder {
+                Some(recorder) => {
+                    ui.label(format!(
+                        "Recording to {}: {} messages",
+                        recorder.path(),
+                        recorder.count()
+                    ));
+                    if ui.button("Stop recording").clicked() {
+                        model.recorder = None;
+                    }
+                }
+                None => {
+                    if ui.button("Start recording").clicked() {
+                        let is_playing_file = model
+                            .player
+                            .as_ref()
+                            .is_some_and(|player| same_file(player.path(), &model.recording_path));
+                        if is_playing_file {
+                            error!(
+                                "Not recording to {}, which is loaded for playback",
+                                model.recording_path
+                            );
+                        } else {
+                            match Recorder::start(&model.recording_path) {
+                                Ok(recorder) => model.recorder = Some(recorder),
+                                Err(e) => error!("Failed to start recording: {e}"),
+                            }
+                        }
+                    }
+                }
+            }
+
+            ui.separator();
+
+            if ui.button("Load for playback").clicked() {
+                let is_recording_file = model
+                    .recorder
+                    .as_ref()
+                    .is_some_and(|recorder| same_file(recorder.path(), &model.recording_path));
+                if is_recording_file {
+                    error!(
+                        "Not playing {}, which is being recorded to",
+                        model.recording_path
+                    );
+                } else {
+                    match Player::load(&model.recording_path) {
+                        Ok(player) => model.player = Some(player),
+                        Err(e) => error!("Failed to load recording: {e}"),
+                    }
+                }
+            }
+
+            if let Some(player) = &mut model.player {
+                ui.label(format!(
+                    "{}: {} messages over {:.1}s",
+                    player.path(),
+                    player.len(),
+                    player.duration().to_f32().unwrap() / 1000.
+                ));
+                ui.horizontal(|ui| {
+                    let label = if player.playing { "Pause" } else { "Play" };
+                    if ui.button(label).clicked() {
+                        player.playing = !player.playing;
+                    }
+                    ui.checkbox(&mut player.looping, "Loop");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Speed:");
+                    let mut speed = player.speed();
+                    if ui
+                        .add(
+                            Slider::new(&mut speed, 0.1..=10.)
+                                .clamp_to_range(true)
+                                .suffix("x"),
+                        )
+                        .changed()
+                    {
+                        if let Err(e) = player.set_speed(speed) {
+                            error!("{e}");
+                        }
+                    }
+                });
+                let mut position = player.position();
+                ui.horizontal(|ui| {
+                    ui.label("Position:");
+                    if ui
+                        .add(Slider::new(&mut position, 0..=player.duration()).suffix("ms"))
+                        .changed()
+                    {
+                        player.seek(position);
+                    }
+                });
+            }
+        });
+
+        // ---------------- AUDIO ANALYSIS SECTION
+
+        ui.collapsing("Audio Analysis", |ui| {
+            let AudioSettings {
+                mapping,
+                bands,
+                sensitivity,
+                min_gap,
+            } = audio;
+
+            ui.horizontal(|ui| {
+                ui.label("WAV file:");
+                ui.text_edit_singleline(&mut model.audio_path);
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Bands:");
+                ui.add(Slider::new(bands, 1..=32));
+            });
+
+            match &model.audio_pending {
+                Some(pending) => {
+                    ui.label(format!("Analysing {}...", pending.path()));
+                }
+                None => {
+                    if ui.button("Load and analyse").clicked() {
+                        model.audio_pending = Some(PendingAudio::start(&model.audio_path, *bands));
+                    }
+                }
+            }
+
+            ComboBox::from_label("Trigger from")
+                .selected_text(mapping.to_string())
+                .show_ui(ui, |ui| {
+                    for named_mapping in AudioMapping::iter() {
+                        let n = named_mapping.to_string();
+                        ui.selectable_value(mapping, named_mapping, n);
+                    }
+                });
+
+            ui.horizontal(|ui| {
+                ui.label("Onset sensitivity:");
+                ui.add(Slider::new(sensitivity, 1. ..=10.).suffix("x"));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Min gap between onsets:");
+                ui.add(Slider::new(min_gap, 10..=2000).suffix("ms"));
+            });
+
+            if let Some(player) = &mut model.audio {
+                ui.label(format!(
+                    "{}: {:.1}s in {} bands",
+                    player.path(),
+                    player.duration().to_f32().unwrap() / 1000.,
+                    player.bands()
+                ));
+                ui.horizontal(|ui| {
+                    let label = if player.playing { "Pause" } else { "Play" };
+                    if ui.button(label).clicked() {
+                        player.playing = !player.playing;
+                    }
+                    ui.checkbox(&mut player.looping, "Loop");
+                });
+                let mut position = player.position();
+                ui.horizontal(|ui| {
+                    ui.label("Position:");
+                    if ui
+                        .add(Slider::new(&mut position, 0..=player.duration()).suffix("ms"))
+                        .changed()
+                    {
+                        player.seek(position);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    for level in player.levels() {
+                        ui.add(egui::ProgressBar::new(*level).desired_width(12.));
+                    }
+                });
+            }
+        });
+
+        // ---------------- CUE LIST SECTION
+        ui.collapsing("Cue List", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("File:");
+                ui.text_edit_singleline(&mut model.cues_path);
+                if ui.button("Load").clicked() {
+                    match Sequencer::load(&model.cues_path) {
+                        Ok(sequencer) => model.sequencer = Some(sequencer),
+                        Err(e) => error!("Failed to load cue list: {e}"),
+                    }
+                }
+            });
+
+            if let Some(sequencer) = &mut model.sequencer {
+                ui.label(format!(
+                    "{}: {} cues",
+                    sequencer.path(),
+                    sequencer.cues().len()
+                ));
+                ui.label(match (sequencer.state(), sequencer.running()) {
+                    (SequencerState::Stopped, _) | (_, None) => String::from("Stopped"),
+                    (state, Some(running)) => format!(
+                        "{} \"{}\" at {:.1}s",
+                        if state == SequencerState::Paused {
+                            "Paused"
+                        } else {
+                            "Running"
+                        },
+                        sequencer.cues()[running].name,
+                        sequencer.elapsed().to_f32().unwrap() / 1000.
+                    ),
+                });
+
+                ui.horizontal(|ui| {
+                    if ui.button("GO").clicked() {
+                        if let Err(e) = sequencer.go() {
+                            error!("Could not GO: {e}");
+                        }
+                    }
+                    match sequencer.state() {
+                        SequencerState::Playing => {
+                            if ui.button("Pause").clicked() {
+                                sequencer.pause();
+                            }
+                        }
+                        SequencerState::Paused => {
+                            if ui.button("Resume").clicked() {
+                                sequencer.resume();
+                            }
+                        }
+                        SequencerState::Stopped => {}
+                    }
+                    if ui.button("Stop").clicked() {
+                        sequencer.stop();
+                    }
+                });
+
+                ui.separator();
+
+                let mut jump_to = None;
+                for (i, cue) in sequencer.cues().iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        let marker = if sequencer.running() == Some(i) {
+                            "▶"
+                        } else if sequencer.standby() == Some(i) {
+                            "•"
+                        } else {
+                            " "
+                        };
+                        ui.label(format!("{marker} {i}: {}", cue.name));
+                        if ui.small_button("standby").clicked() {
+                            jump_to = Some(i);
+                        }
+                    });
+                }
+                if let Some(cue) = jump_to {
+                    sequencer.jump(cue);
+                }
+            }
+        });
+
+        // ---------------- SCENES SECTION
+        ui.collapsing("Scenes", |ui| {
+            ui.label(format!(
+                "{}: {} scenes",
+                model.scenes.path(),
+                model.scenes.scenes().len()
+            ));
+
+            ui.horizontal(|ui| {
+                ui.label("Crossfade:");
+                ui.add(Slider::new(scene_fade_duration, 0..=30000).suffix("ms"));
+            });
+            ComboBox::from_label("Crossfade style")
+                .selected_text(scene_fade_style.to_string())
+                .show_ui(ui, |ui| {
+                    for named_style in EaseStyle::iter() {
+                        let n = named_style.to_string();
+                        ui.selectable_value(scene_fade_style, named_style, n);
+                    }
+                });
+
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut model.scene_name);
+                if ui.button("Capture").clicked() {
+                    if let Err(e) = model.scenes.capture(&model.scene_name, &model.particles) {
+                        error!("Failed to capture scene: {e}");
+                    }
+                }
+            });
+
+            ui.separator();
+
+            let mut to_delete = None;
+            for scene in model.scenes.scenes() {
+                ui.horizontal(|ui| {
+                    ui.label(&scene.name);
+                    if ui.small_button("recall").clicked() {
+                        scene.recall(
+                            &mut model.particles,
+                            *scene_fade_duration,
+                            *scene_fade_style,
+                        );
+                    }
+                    if ui.small_button("delete").clicked() {
+                        to_delete = Some(scene.name.clone());
+                    }
+                });
+            }
+            if let Some(name) = to_delete {
+                if let Err(e) = model.scenes.delete(&name) {
+                    error!("Failed to delete scene: {e}");
+                }
+            }
         });
 
         // ---------------- ARTNET SECTION
@@ -185,7 +1015,13 @@ pub fn build_ui(model: &mut Model, since_start: Duration, window_rect: Rect) {
                                 window_rect.w() * DEFAULT_WIDTH_RATIO,
                                 window_rect.h() * 0.2,
                                 fixture_order,
-                            )
+                            );
+                            model.installations.lay_out_parked(
+                                fixture_order.len(),
+                                window_rect.w() * DEFAULT_WIDTH_RATIO,
+                                window_rect.h() * 0.2,
+                                fixture_order,
+                            );
                         }
                         Err(()) => error!("Failed to parse fixture order string"),
                     }
@@ -214,6 +1050,98 @@ pub fn build_ui(model: &mut Model, since_start: Duration, window_rect: Rect) {
             }
         });
 
+        // ---------------- PROFILES SECTION
+        ui.collapsing("Profiles", |ui| {
+            ui.label(format!(
+                "{}: {} profiles",
+                model.profiles.path(),
+                model.profiles.names().len()
+            ));
+            match (
+                model.profiles.active(),
+                model.profiles.transition_progress(),
+            ) {
+                (Some(active), Some(progress)) => {
+                    ui.label(format!(
+                        "Switching to \"{active}\" ({:.0}%)",
+                        progress * 100.
+                    ));
+                }
+                (Some(active), None) => {
+                    ui.label(format!("Active: \"{active}\""));
+                }
+                (None, _) => {}
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("Transition:");
+                ui.add(Slider::new(&mut model.profiles.default_transition, 0..=30000).suffix("ms"));
+            });
+
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut model.profile_name);
+                if ui.button("Save current").clicked() {
+                    if let Err(e) = model.profiles.save(&model.profile_name, &model.settings) {
+                        error!("Failed to save profile: {e}");
+                    }
+                }
+            });
+
+            ui.separator();
+
+            let mut to_switch = None;
+            let mut to_delete = None;
+            for name in model.profiles.names() {
+                ui.horizontal(|ui| {
+                    ui.label(&name);
+                    if ui.small_button("switch").clicked() {
+                        to_switch = Some(name.clone());
+                    }
+                    if ui.small_button("delete").clicked() {
+                        to_delete = Some(name.clone());
+                    }
+                });
+            }
+            if let Some(name) = to_switch {
+                let previous_order = model.settings.fixture_order;
+                let previous_count = model.settings.chimes_count;
+                let previous_mapping = model.settings.lights_lookup_mapping;
+                let transition = model.profiles.default_transition;
+                if let Err(e) = model
+                    .profiles
+                    .switch(&name, &mut model.settings, transition)
+                {
+                    error!("Failed to switch profile: {e}");
+                }
+                if model.settings.lights_lookup_mapping != previous_mapping {
+                    model
+                        .artnet
+                        .create_brightness_mapping(&model.settings.lights_lookup_mapping);
+                }
+                if model.settings.fixture_order != previous_order
+                    || model.settings.chimes_count != previous_count
+                {
+                    model.particles = build_layout(
+                        model.settings.chimes_count,
+                        window_rect.w() * DEFAULT_WIDTH_RATIO,
+                        window_rect.h() * 0.2,
+                        &model.settings.fixture_order,
+                    );
+                    model.installations.lay_out_parked(
+                        model.settings.chimes_count,
+                        window_rect.w() * DEFAULT_WIDTH_RATIO,
+                        window_rect.h() * 0.2,
+                        &model.settings.fixture_order,
+                    );
+                }
+            }
+            if let Some(name) = to_delete {
+                if let Err(e) = model.profiles.delete(&name) {
+                    error!("Failed to delete profile: {e}");
+                }
+            }
+        });
+
         // ---------------- SAVE/LOAD
         ui.separator();
 